@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern "C" fn identity(x: i32) -> i32 {
+    x
+}
@@ -0,0 +1,384 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::cache;
+use crate::cargo;
+use crate::error::Result;
+use crate::features;
+use crate::output::BuildOutput;
+use crate::overlay::Overlay;
+use crate::run;
+
+#[derive(Clone, Debug)]
+pub(crate) enum Source {
+    File(PathBuf),
+    Inline { name: String, code: String },
+    Example(String),
+    CurrentProject,
+    Package(String),
+    CrateAt(PathBuf),
+}
+
+/// Configures and builds a cdylib.
+///
+/// ```no_run
+/// let output = test_cdylib::CdylibBuilder::file("tests/cdylibs/api_test.rs")
+///     .features(["extended-api"])
+///     .env("MY_BUILD_VAR", "1")
+///     .build()
+///     .unwrap();
+///
+/// let dylib = dlopen::symbor::Library::open(&output.artifact).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CdylibBuilder {
+    pub(crate) source: Source,
+    pub(crate) features: Option<Vec<String>>,
+    pub(crate) no_default_features: bool,
+    pub(crate) all_features: bool,
+    pub(crate) profile: String,
+    pub(crate) target: Option<String>,
+    pub(crate) envs: Vec<(OsString, OsString)>,
+    pub(crate) rustflags: Vec<String>,
+    pub(crate) cargo_args: Vec<String>,
+    pub(crate) build_script: Option<PathBuf>,
+    pub(crate) overlays: Vec<Overlay>,
+    pub(crate) all_dependencies: bool,
+    pub(crate) dependencies: Vec<String>,
+    pub(crate) force_cdylib: bool,
+    pub(crate) quiet: bool,
+    /// Never print cargo's output, for builds expected to fail.
+    pub(crate) silent: bool,
+    pub(crate) deny_warnings: bool,
+    /// Copy the artifact into this directory next to it instead of the one
+    /// named after the configuration, see `build_feature_matrix`.
+    pub(crate) artifact_subdir: Option<String>,
+}
+
+impl CdylibBuilder {
+    fn new(source: Source) -> Self {
+        CdylibBuilder {
+            source,
+            features: None,
+            no_default_features: false,
+            all_features: false,
+            profile: "dev".to_owned(),
+            target: None,
+            envs: Vec::new(),
+            rustflags: Vec::new(),
+            cargo_args: Vec::new(),
+            build_script: None,
+            overlays: Vec::new(),
+            all_dependencies: false,
+            dependencies: Vec::new(),
+            force_cdylib: false,
+            quiet: false,
+            silent: false,
+            deny_warnings: false,
+            artifact_subdir: None,
+        }
+    }
+
+    /// The default configuration, used by the batch build functions.
+    pub(crate) fn defaults() -> Self {
+        Self::new(Source::CurrentProject)
+    }
+
+    /// Whether features were selected explicitly, which disables detecting
+    /// those of the running test.
+    pub(crate) fn selects_features(&self) -> bool {
+        self.features.is_some() || self.no_default_features || self.all_features
+    }
+
+    /// The features of the running test, unless features were selected
+    /// explicitly.
+    pub(crate) fn detected_features(&self) -> Option<Vec<String>> {
+        if self.selects_features() {
+            None
+        } else {
+            features::find()
+        }
+    }
+
+    /// The directory the artifact is copied into, named after a hash of
+    /// everything but the source. Sources that build the same file, like
+    /// different paths to the same crate, share the copy.
+    fn config_subdir(&self) -> String {
+        let mut config = self.clone();
+        config.source = Source::CurrentProject;
+        let hash = run::fnv1a(format!("{:?}", config).as_bytes());
+        format!("config-{:016x}", hash)
+    }
+
+    /// Builds the given file as a cdylib.
+    pub fn file<P: AsRef<Path>>(path: P) -> Self {
+        Self::new(Source::File(path.as_ref().to_owned()))
+    }
+
+    /// Builds the given source code as a cdylib, as if it were a file.
+    ///
+    /// The generated project is named after `name` and a hash of `code`, so
+    /// identical sources are only built once.
+    pub fn source(name: &str, code: &str) -> Self {
+        Self::new(Source::Inline {
+            name: name.to_owned(),
+            code: code.to_owned(),
+        })
+    }
+
+    /// Builds the given example of the current project as a cdylib.
+    pub fn example(name: &str) -> Self {
+        Self::new(Source::Example(name.to_owned()))
+    }
+
+    /// Builds the current project as a cdylib.
+    pub fn current_project() -> Self {
+        Self::new(Source::CurrentProject)
+    }
+
+    /// Builds the library of the given workspace package as a cdylib.
+    pub fn package(name: &str) -> Self {
+        Self::new(Source::Package(name.to_owned()))
+    }
+
+    /// Builds the library of the crate at the given path as a cdylib. The
+    /// path is either the crate's directory or its `Cargo.toml`, relative to
+    /// the current project.
+    pub fn crate_at<P: AsRef<Path>>(path: P) -> Self {
+        Self::new(Source::CrateAt(path.as_ref().to_owned()))
+    }
+
+    /// Enables the given features in addition to the default features.
+    ///
+    /// By default the features of the running test are detected and used.
+    /// Setting this disables that detection.
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features = Some(features.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Disables the default features.
+    ///
+    /// Like [`features`](Self::features), this disables the detection of the
+    /// running test's features.
+    pub fn no_default_features(mut self) -> Self {
+        self.no_default_features = true;
+        self
+    }
+
+    /// Enables every feature.
+    ///
+    /// Like [`features`](Self::features), this disables the detection of the
+    /// running test's features.
+    pub fn all_features(mut self) -> Self {
+        self.all_features = true;
+        self
+    }
+
+    /// Builds with the given cargo profile, e.g. `"release"` or a custom
+    /// profile defined in the workspace's `Cargo.toml`. Defaults to `"dev"`.
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = profile.to_owned();
+        self
+    }
+
+    /// Builds with the `release` profile.
+    pub fn release(self) -> Self {
+        self.profile("release")
+    }
+
+    /// Builds for the given target triple instead of the host.
+    ///
+    /// The artifact is found by the target's naming conventions, e.g. `.wasm`
+    /// for `wasm32-unknown-unknown`. On targets without cdylib support, like
+    /// `x86_64-unknown-linux-musl`, a static library is returned instead if
+    /// the crate builds one.
+    pub fn target(mut self, triple: &str) -> Self {
+        self.target = Some(triple.to_owned());
+        self
+    }
+
+    /// Sets an environment variable for the cargo invocation.
+    pub fn env<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Passes an extra flag to rustc.
+    ///
+    /// The flag is added to the `build.rustflags` of cargo's config files. If
+    /// the `RUSTFLAGS` environment variable is set, cargo ignores those and
+    /// the flag is added to `RUSTFLAGS` instead. Cargo also ignores
+    /// `build.rustflags` for a target with its own `target.<triple>.rustflags`.
+    pub fn rustflag(mut self, flag: &str) -> Self {
+        self.rustflags.push(flag.to_owned());
+        self
+    }
+
+    /// Builds the [`current_project`](Self::current_project) or an
+    /// [`example`](Self::example) as a cdylib even if its `crate-type`
+    /// doesn't include it, with `cargo rustc --crate-type cdylib`.
+    ///
+    /// The library is built in a target directory of its own, so that it
+    /// doesn't disturb the normal build of the project.
+    pub fn force_cdylib(mut self) -> Self {
+        self.force_cdylib = true;
+        self
+    }
+
+    /// Only prints cargo's output and the compiler's diagnostics if the build
+    /// fails. They're still available from
+    /// [`BuildOutput::diagnostics`](crate::BuildOutput::diagnostics).
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    /// Fails the build with [`Error::Warnings`](crate::Error::Warnings) if
+    /// the compiler emits any warnings for the cdylib's package.
+    pub fn deny_warnings(mut self) -> Self {
+        self.deny_warnings = true;
+        self
+    }
+
+    /// Passes an extra argument to `cargo build`.
+    pub fn cargo_arg(mut self, arg: &str) -> Self {
+        self.cargo_args.push(arg.to_owned());
+        self
+    }
+
+    /// Uses the given file as the build script of a [`file`](Self::file) or
+    /// [`source`](Self::source) fixture.
+    ///
+    /// The build-dependencies of the current project are made available to
+    /// it.
+    pub fn build_script<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.build_script = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Makes the given dependency or dev-dependency of the current project
+    /// available to a [`file`](Self::file) or [`source`](Self::source)
+    /// fixture, even if it isn't found in the fixture's source.
+    ///
+    /// Fixtures only get the dependencies their source refers to by default,
+    /// e.g. with `use serde::Serialize` or `extern crate serde`.
+    pub fn dependency(mut self, name: &str) -> Self {
+        self.dependencies.push(name.to_owned());
+        self
+    }
+
+    /// Makes every dependency and dev-dependency of the current project
+    /// available to a [`file`](Self::file) or [`source`](Self::source)
+    /// fixture, instead of only those it uses.
+    pub fn all_dependencies(mut self) -> Self {
+        self.all_dependencies = true;
+        self
+    }
+
+    /// Merges the given table over the `Cargo.toml` generated for a
+    /// [`file`](Self::file) or [`source`](Self::source) fixture, e.g. to set
+    /// `lib.name` or `package.metadata`.
+    ///
+    /// Tables are merged recursively. Setting a key the generated manifest
+    /// already sets to a different value fails the build with
+    /// [`Error::ManifestConflict`](crate::Error::ManifestConflict).
+    pub fn manifest_overlay(mut self, overlay: toml::Value) -> Self {
+        self.overlays.push(Overlay::Value(overlay));
+        self
+    }
+
+    /// Like [`manifest_overlay`](Self::manifest_overlay), but takes the
+    /// overlay as a TOML string.
+    pub fn manifest_overlay_str(mut self, overlay: &str) -> Self {
+        self.overlays.push(Overlay::Toml(overlay.to_owned()));
+        self
+    }
+
+    /// Builds the cdylib.
+    ///
+    /// Builds with the same configuration are only run once per process;
+    /// later calls wait for the first one and return the same output.
+    ///
+    /// The artifact is copied into a directory named after the configuration,
+    /// so that building another configuration later doesn't overwrite it.
+    pub fn build(&self) -> Result<BuildOutput> {
+        let mut builder = self.clone();
+        if builder.artifact_subdir.is_none() {
+            builder.artifact_subdir = Some(self.config_subdir());
+        }
+        let builder = &builder;
+        cache::memoize(format!("{:?}", self), || match &builder.source {
+            Source::File(path) => run::run(path, builder),
+            Source::Inline { name, code } => run::run_source(name, code, builder),
+            Source::Example(name) => cargo::build_example(name, builder),
+            Source::CurrentProject => cargo::build_self_cdylib(builder),
+            Source::Package(name) => cargo::build_package(name, builder),
+            Source::CrateAt(path) => cargo::build_crate_at(path, builder),
+        })
+    }
+
+    /// Builds the cdylib once for each of the given feature sets, with only
+    /// the features of the set enabled, and returns each set with its output.
+    ///
+    /// Every artifact is copied into a directory of its own, so that later
+    /// builds don't overwrite it.
+    ///
+    /// ```no_run
+    /// let builds = test_cdylib::CdylibBuilder::current_project()
+    ///     .build_feature_matrix(vec![vec![], vec!["extended-api"]])
+    ///     .unwrap();
+    /// for (features, output) in builds {
+    ///     let dylib = dlopen::symbor::Library::open(&output.artifact).unwrap();
+    ///     let extended = unsafe { dylib.symbol::<extern "C" fn()>("extended") };
+    ///     assert_eq!(extended.is_ok(), features.contains(&"extended-api".to_owned()));
+    /// }
+    /// ```
+    pub fn build_feature_matrix<I, F, S>(
+        &self,
+        matrix: I,
+    ) -> Result<Vec<(Vec<String>, BuildOutput)>>
+    where
+        I: IntoIterator<Item = F>,
+        F: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        matrix
+            .into_iter()
+            .map(|features| {
+                let mut features: Vec<String> = features.into_iter().map(Into::into).collect();
+                features.sort();
+                features.dedup();
+
+                let mut builder = self.clone().no_default_features();
+                builder.all_features = false;
+                builder.artifact_subdir = Some(if features.is_empty() {
+                    "no-features".to_owned()
+                } else {
+                    format!("features-{}", features.join(","))
+                });
+                let output = builder.features(features.clone()).build()?;
+                Ok((features, output))
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_selects_features() {
+    let builder = CdylibBuilder::file("identity.rs");
+    assert!(!builder.selects_features());
+    assert!(builder.clone().features(["a"]).selects_features());
+    assert!(builder.clone().no_default_features().selects_features());
+    assert!(builder.clone().all_features().selects_features());
+    assert_eq!(builder.all_features().detected_features(), None);
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap as Map;
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::error::Result;
+use crate::output::BuildOutput;
+
+type Slot = Arc<Mutex<Option<BuildOutput>>>;
+
+static BUILDS: Mutex<Map<String, Slot>> = Mutex::new(Map::new());
+
+/// Runs `build` once per key within this process. Concurrent callers with the
+/// same key wait for the first one to finish and share its output. Failed
+/// builds aren't remembered, so a later caller will try again.
+pub fn memoize<F>(key: String, build: F) -> Result<BuildOutput>
+where
+    F: FnOnce() -> Result<BuildOutput>,
+{
+    let slot = BUILDS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(key)
+        .or_default()
+        .clone();
+    let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some(output) = &*slot {
+        return Ok(output.clone());
+    }
+    let output = build()?;
+    *slot = Some(output.clone());
+    Ok(output)
+}
+
+/// Takes an exclusive advisory lock on the given directory, which is held
+/// until the returned file is dropped. This keeps separate test binaries from
+/// writing the same generated project at once.
+pub fn lock_dir(dir: &Path) -> Result<File> {
+    fs::create_dir_all(dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(".test-cdylib.lock"))?;
+    file.lock()?;
+    Ok(file)
+}
@@ -1,19 +1,32 @@
-use cargo_metadata::Message;
+use cargo_metadata::{Artifact, Message};
 use serde::Deserialize;
-use serde_json::Map;
-use std::io::{BufReader, Cursor};
-use std::path::PathBuf;
-use std::process::{Command, Output, Stdio};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
+use crate::builder::CdylibBuilder;
+use crate::diagnostic::Diagnostic;
 use crate::error::{Error, Result};
-use crate::features;
-use crate::run::Project;
+use crate::output::BuildOutput;
+use crate::run::{self, Project};
 use crate::rustflags;
+use crate::target;
 
 #[derive(Deserialize)]
 pub struct Metadata {
     pub target_directory: PathBuf,
     pub workspace_root: PathBuf,
+    pub packages: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub manifest_path: PathBuf,
 }
 
 fn raw_cargo() -> Command {
@@ -26,92 +39,476 @@ fn cargo_supports_offline() -> bool {
         .arg("--offline")
         .output()
         .ok()
-        .map_or(false, |res| res.status.success())
+        .is_some_and(|res| res.status.success())
+}
+
+fn cargo_build(builder: &CdylibBuilder, features: &Option<Vec<String>>) -> Command {
+    cargo_command("build", builder, features)
 }
 
-fn cargo_build(features: &Option<Vec<String>>) -> Command {
+fn cargo_command(
+    subcommand: &str,
+    builder: &CdylibBuilder,
+    features: &Option<Vec<String>>,
+) -> Command {
     let mut cmd = raw_cargo();
     if cargo_supports_offline() {
         cmd.arg("--offline");
     }
-    cmd.arg("build")
+    cmd.arg(subcommand)
         .arg("--message-format=json")
-        .args(feature_args(features));
-    rustflags::set_env(&mut cmd);
+        .args(feature_args(builder, features))
+        .args(profile_args(&builder.profile));
+    if let Some(target) = &builder.target {
+        cmd.arg("--target").arg(target);
+    }
+    cmd.args(&builder.cargo_args);
+    cmd.envs(builder.envs.iter().map(|(k, v)| (k, v)));
+    rustflags::set_env(&mut cmd, &builder.rustflags);
     cmd
 }
 
-pub fn build_cdylib(project: &Project) -> Result<PathBuf> {
-    parse_output(
-        cargo_build(&project.features)
-            .current_dir(&project.dir)
-            .env("CARGO_TARGET_DIR", path!(&project.dir / "target"))
-            .stderr(Stdio::inherit())
-            .output()
-            .map_err(Error::Cargo)?,
-    )
+pub fn build_cdylib(project: &Project, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    build_cdylibs(&[project], builder)?.remove(0)
 }
 
-pub fn build_self_cdylib() -> Result<PathBuf> {
-    parse_output(
-        cargo_build(&features::find())
-            .arg("--lib")
-            .stderr(Stdio::inherit())
-            .output()
-            .map_err(Error::Cargo)?,
+pub fn build_cdylibs(
+    projects: &[&Project],
+    builder: &CdylibBuilder,
+) -> Result<Vec<Result<BuildOutput>>> {
+    // All fixtures of a crate mirror the same features, so the first one's
+    // selection applies to every package.
+    let mut cmd = cargo_build(builder, &projects[0].features);
+    if projects.len() > 1 {
+        cmd.arg("--keep-going");
+    }
+    for project in projects {
+        cmd.arg("--package").arg(&project.name);
+    }
+    let workspace_dir = &projects[0].workspace_dir;
+    cmd.current_dir(workspace_dir)
+        .env("CARGO_TARGET_DIR", path!(workspace_dir / "target"));
+    let invocation = Invocation::run(cmd, builder)?;
+    Ok(projects
+        .iter()
+        .map(|project| {
+            let artifact = invocation.find(&path!(project.dir / "Cargo.toml"), None);
+            invocation.output(artifact, builder, Some(project))
+        })
+        .collect())
+}
+
+pub fn build_self_cdylib(builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let crate_name = env::var("CARGO_PKG_NAME").map_err(Error::PkgName)?;
+    let manifest_path = self_manifest_path()?;
+    let target = Target::Lib(&manifest_path);
+    build_target(
+        &crate_name,
+        target,
+        &["--lib"],
+        &builder.detected_features(),
+        builder,
     )
 }
 
-pub fn build_example(name: &str) -> Result<PathBuf> {
-    parse_output(
-        cargo_build(&features::find())
-            .arg("--example")
-            .arg(name)
-            .stderr(Stdio::inherit())
-            .output()
-            .map_err(Error::Cargo)?,
+pub fn build_example(name: &str, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let crate_name = env::var("CARGO_PKG_NAME").map_err(Error::PkgName)?;
+    let manifest_path = self_manifest_path()?;
+    let target = Target::Example(&manifest_path, name);
+    let args = ["--example", name];
+    build_target(
+        &crate_name,
+        target,
+        &args,
+        &builder.detected_features(),
+        builder,
     )
 }
 
-pub fn parse_output(result: Output) -> Result<PathBuf> {
-    let mut artifact = None;
-    for message in Message::parse_stream(Cursor::new(result.stdout)) {
-        match message? {
-            Message::CompilerMessage(m) => eprintln!("{}", m),
-            Message::CompilerArtifact(a) => artifact = Some(a),
-            _ => (),
+pub fn build_package(name: &str, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let package = metadata()?
+        .packages
+        .into_iter()
+        .find(|package| package.name == name)
+        .ok_or_else(|| Error::PackageNotFound {
+            name: name.to_owned(),
+        })?;
+    let manifest_path = package.manifest_path.as_os_str().to_string_lossy();
+    let args = ["--manifest-path", &manifest_path, "--lib"];
+    let target = Target::Lib(&package.manifest_path);
+    // The running test's features belong to a different package.
+    build_target(name, target, &args, &None, builder)
+}
+
+pub fn build_crate_at(path: &Path, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let source_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok_or(Error::ProjectDir)?;
+    let mut manifest_path = source_dir.join(path);
+    if manifest_path.is_dir() {
+        manifest_path.push("Cargo.toml");
+    }
+    run::check_exists(&manifest_path)?;
+    // Cargo reports the canonical path of the manifest.
+    let manifest_path =
+        fs::canonicalize(&manifest_path).map_err(|err| Error::Open(manifest_path.clone(), err))?;
+
+    let name = manifest_path
+        .parent()
+        .and_then(Path::file_name)
+        .map_or_else(|| "crate".into(), |name| name.to_string_lossy());
+    let manifest_arg = manifest_path.as_os_str().to_string_lossy();
+    let args = ["--manifest-path", &manifest_arg, "--lib"];
+    build_target(&name, Target::Lib(&manifest_path), &args, &None, builder)
+}
+
+fn self_manifest_path() -> Result<PathBuf> {
+    let source_dir = env::var_os("CARGO_MANIFEST_DIR").ok_or(Error::ProjectDir)?;
+    Ok(path!(PathBuf::from(source_dir) / "Cargo.toml"))
+}
+
+/// Builds a single target of the package `name`.
+fn build_target(
+    name: &str,
+    target: Target,
+    target_args: &[&str],
+    features: &Option<Vec<String>>,
+    builder: &CdylibBuilder,
+) -> Result<BuildOutput> {
+    let mut cmd = if builder.force_cdylib {
+        cargo_command("rustc", builder, features)
+    } else {
+        cargo_build(builder, features)
+    };
+    cmd.args(target_args);
+    if builder.force_cdylib {
+        cmd.arg("--crate-type").arg("cdylib");
+        // Building with another crate type would otherwise invalidate the
+        // normal build of the target, and the other way around.
+        let target_dir = path!(metadata()?.target_directory / "cdylibs" / name / "crate-type");
+        cmd.env("CARGO_TARGET_DIR", target_dir);
+    }
+    let invocation = Invocation::run(cmd, builder)?;
+    let artifact = match target {
+        Target::Lib(manifest_path) => invocation.find(manifest_path, None),
+        Target::Example(manifest_path, name) => invocation.find(manifest_path, Some(name)),
+    };
+    invocation.output(artifact, builder, None)
+}
+
+pub fn build_examples(names: &[&str], builder: &CdylibBuilder) -> Result<Vec<Result<BuildOutput>>> {
+    let manifest_path = self_manifest_path()?;
+    let mut cmd = cargo_build(builder, &builder.detected_features());
+    cmd.arg("--keep-going");
+    for name in names {
+        cmd.arg("--example").arg(name);
+    }
+    let invocation = Invocation::run(cmd, builder)?;
+    Ok(names
+        .iter()
+        .map(|name| {
+            let artifact = invocation.find(&manifest_path, Some(name));
+            invocation.output(artifact, builder, None)
+        })
+        .collect())
+}
+
+/// The target a single build is expected to produce.
+enum Target<'a> {
+    /// The library of the package with the given manifest.
+    Lib(&'a Path),
+    /// The named example of the package with the given manifest.
+    Example(&'a Path, &'a str),
+}
+
+/// A finished cargo build.
+struct Invocation {
+    command: String,
+    status: ExitStatus,
+    stderr: String,
+    artifacts: Vec<Artifact>,
+    /// Each diagnostic with the id of the package it belongs to.
+    diagnostics: Vec<(String, Diagnostic)>,
+    duration: Duration,
+    /// The target triple that was built for.
+    triple: String,
+}
+
+impl Invocation {
+    fn run(mut cmd: Command, builder: &CdylibBuilder) -> Result<Self> {
+        let triple = match &builder.target {
+            Some(triple) => triple.clone(),
+            None => target::host()?,
+        };
+        let command = command_line(&cmd);
+        let start = Instant::now();
+        let output = cmd
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|source| Error::CargoMissing {
+                command: command.clone(),
+                source,
+            })?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let mut artifacts = Vec::new();
+        let mut diagnostics = Vec::new();
+        for message in Message::parse_stream(Cursor::new(output.stdout)) {
+            match message? {
+                Message::CompilerMessage(m) => {
+                    diagnostics.push((m.package_id.repr, Diagnostic::from(m.message)))
+                }
+                Message::CompilerArtifact(a) => artifacts.push(a),
+                _ => (),
+            }
+        }
+
+        // Replay cargo's own output so it still shows up in the test log,
+        // unless it's only wanted for failed builds.
+        if !builder.silent && (!builder.quiet || !output.status.success()) {
+            eprint!("{}", stderr);
+            for (_, diagnostic) in &diagnostics {
+                eprint_diagnostic(diagnostic);
+            }
         }
+
+        // Only a failure to run cargo at all is an error here, so that
+        // batch builds can still report the artifacts that were built.
+        Ok(Invocation {
+            command,
+            status: output.status,
+            stderr,
+            artifacts,
+            diagnostics,
+            duration: start.elapsed(),
+            triple,
+        })
     }
 
-    if !result.status.success() {
-        return Err(Error::CargoFail);
+    /// Finds the cdylib of the package with the given manifest, or of its
+    /// example if one is named, rather than e.g. that of a dependency. On
+    /// targets without cdylib support, its static library is accepted too.
+    ///
+    /// Cargo reports canonical manifest paths, so the given path is matched
+    /// after resolving symlinks too, e.g. those of a target dir in `/tmp` on
+    /// macOS.
+    fn find(&self, manifest_path: &Path, example: Option<&str>) -> Option<&Artifact> {
+        let canonical_path = fs::canonicalize(manifest_path).ok();
+        let is_manifest = |path: &Path| {
+            path == manifest_path
+                || canonical_path.is_some() && fs::canonicalize(path).ok() == canonical_path
+        };
+        self.artifacts.iter().rev().find(|artifact| {
+            let is_example = artifact.target.kind.iter().any(|kind| kind == "example");
+            let is_target = match example {
+                Some(name) => is_example && artifact.target.name == name,
+                None => !is_example,
+            };
+            let is_library = artifact.target.crate_types.iter().any(|crate_type| {
+                crate_type == "cdylib"
+                    || crate_type == "staticlib" && !target::supports_cdylib(&self.triple)
+            });
+            is_target && is_library && is_manifest(artifact.manifest_path.as_std_path())
+        })
     }
-    match artifact {
-        Some(artifact) => artifact
-            .filenames
-            .into_iter()
-            .filter(|filename| match filename.extension() {
-                Some("dll") | Some("dylib") | Some("so") => true,
-                _ => false,
+
+    /// The diagnostics of the package the artifact belongs to. Without an
+    /// artifact, those of the generated project's package, or all of them if
+    /// there's no project either, as the build may have failed anywhere.
+    fn diagnostics_of(
+        &self,
+        artifact: Option<&Artifact>,
+        project: Option<&Project>,
+    ) -> Vec<Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|(package_id, _)| match (artifact, project) {
+                (Some(artifact), _) => artifact.package_id.repr == *package_id,
+                (None, Some(project)) => package_name(package_id) == project.name,
+                (None, None) => true,
             })
-            .next()
-            .ok_or(Error::CdylibNotFound)
-            .map(|a| a.into_std_path_buf()),
-        None => Err(Error::CargoFail),
+            .map(|(_, diagnostic)| diagnostic.clone())
+            .collect()
+    }
+
+    fn output(
+        &self,
+        artifact: Option<&Artifact>,
+        builder: &CdylibBuilder,
+        project: Option<&Project>,
+    ) -> Result<BuildOutput> {
+        let project_dir = project.map(|project| project.dir.clone());
+        let diagnostics = self.diagnostics_of(artifact, project);
+        let artifact = match artifact {
+            Some(artifact) => artifact,
+            None if !self.status.success() => {
+                return Err(Error::CompileError {
+                    command: self.command.clone(),
+                    status: self.status,
+                    stderr: self.stderr.clone(),
+                    diagnostics,
+                    project_dir,
+                })
+            }
+            None => {
+                return Err(Error::CdylibNotFound {
+                    command: self.command.clone(),
+                    status: self.status,
+                    stderr: self.stderr.clone(),
+                    diagnostics,
+                    project_dir,
+                })
+            }
+        };
+
+        if builder.deny_warnings && diagnostics.iter().any(Diagnostic::is_warning) {
+            if builder.quiet {
+                diagnostics.iter().for_each(eprint_diagnostic);
+            }
+            return Err(Error::Warnings {
+                command: self.command.clone(),
+                diagnostics,
+                project_dir,
+            });
+        }
+
+        let filenames: Vec<PathBuf> = artifact
+            .filenames
+            .iter()
+            .map(|filename| filename.clone().into_std_path_buf())
+            .collect();
+        let triple = &self.triple;
+        let find = |extension: &str| {
+            filenames
+                .iter()
+                .find(|filename| filename.extension() == Some(OsStr::new(extension)))
+        };
+        let cdylib = find(target::cdylib_extension(triple)).or_else(|| {
+            if target::supports_cdylib(triple) {
+                None
+            } else {
+                find(target::staticlib_extension(triple))
+            }
+        });
+        match cdylib {
+            Some(cdylib) => Ok(BuildOutput {
+                artifact: match &builder.artifact_subdir {
+                    Some(subdir) => copy_artifact(cdylib, subdir)?,
+                    None => cdylib.clone(),
+                },
+                filenames: filenames.clone(),
+                package_id: artifact.package_id.repr.clone(),
+                target_name: artifact.target.name.clone(),
+                crate_types: artifact.target.crate_types.clone(),
+                profile: builder.profile.clone(),
+                target: triple.clone(),
+                fresh: artifact.fresh,
+                duration: self.duration,
+                diagnostics,
+            }),
+            None => Err(Error::CdylibNotFound {
+                command: self.command.clone(),
+                status: self.status,
+                stderr: self.stderr.clone(),
+                diagnostics,
+                project_dir,
+            }),
+        }
+    }
+}
+
+fn eprint_diagnostic(diagnostic: &Diagnostic) {
+    match &diagnostic.rendered {
+        Some(rendered) => eprint!("{}", rendered),
+        None => eprintln!("{}: {}", diagnostic.level, diagnostic.message),
     }
 }
 
+/// Copies the artifact into `subdir` of its directory, where builds with
+/// other settings won't overwrite it.
+fn copy_artifact(artifact: &Path, subdir: &str) -> Result<PathBuf> {
+    let dir = artifact.parent().unwrap().join(subdir);
+    fs::create_dir_all(&dir)?;
+    let copy = dir.join(artifact.file_name().unwrap());
+    // Replace rather than overwrite the copy, which may still be loaded.
+    // Other test binaries may be copying the same configuration.
+    let partial = dir.join(format!(".partial-{}", std::process::id()));
+    fs::copy(artifact, &partial)?;
+    fs::rename(&partial, &copy)?;
+    Ok(copy)
+}
+
 pub fn metadata() -> Result<Metadata> {
-    let output = raw_cargo()
-        .arg("metadata")
+    let mut cmd = raw_cargo();
+    cmd.arg("metadata")
         .arg("--format-version=1")
-        .output()
-        .map_err(Error::Cargo)?;
+        .arg("--no-deps");
+    let command = command_line(&cmd);
+    let output = cmd.output().map_err(|source| Error::CargoMissing {
+        command: command.clone(),
+        source,
+    })?;
 
-    serde_json::from_slice(&output.stdout).map_err(Error::Metadata)
+    serde_json::from_slice(&output.stdout).map_err(|source| Error::Metadata {
+        command,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        source,
+    })
 }
 
-fn feature_args(features: &Option<Vec<String>>) -> Vec<String> {
+fn command_line(cmd: &Command) -> String {
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    line
+}
+
+fn profile_args(profile: &str) -> Vec<String> {
+    match profile {
+        "dev" => Vec::new(),
+        "release" => vec!["--release".to_owned()],
+        profile => vec!["--profile".to_owned(), profile.to_owned()],
+    }
+}
+
+/// The name of the package with the given id, which is either a package id
+/// spec like `path+file:///dir#name@0.1.0`, or `name 0.1.0 (source)` in older
+/// versions of cargo.
+fn package_name(package_id: &str) -> &str {
+    if let Some((name, _)) = package_id.split_once(' ') {
+        return name;
+    }
+    let (url, fragment) = package_id.split_once('#').unwrap_or((package_id, ""));
+    match fragment.split_once('@') {
+        Some((name, _)) => name,
+        // The name is left out if it's the last segment of the URL.
+        None => {
+            let url = url.split('?').next().unwrap_or(url);
+            url.rsplit('/').next().unwrap_or(url)
+        }
+    }
+}
+
+fn feature_args(builder: &CdylibBuilder, features: &Option<Vec<String>>) -> Vec<String> {
+    if builder.selects_features() {
+        let mut args = Vec::new();
+        if builder.no_default_features {
+            args.push("--no-default-features".to_owned());
+        }
+        if builder.all_features {
+            args.push("--all-features".to_owned());
+        }
+        match &builder.features {
+            Some(features) if !features.is_empty() => {
+                args.push("--features".to_owned());
+                args.push(features.join(","));
+            }
+            _ => {}
+        }
+        return args;
+    }
     match features {
         Some(features) => vec![
             "--no-default-features".to_owned(),
@@ -121,3 +518,23 @@ fn feature_args(features: &Option<Vec<String>>) -> Vec<String> {
         None => Vec::new(),
     }
 }
+
+#[test]
+fn test_package_name() {
+    assert_eq!(
+        package_name("path+file:///ws/fixtures/identity#test-cdylib-cdylib-identity@0.0.0"),
+        "test-cdylib-cdylib-identity"
+    );
+    assert_eq!(
+        package_name("path+file:///ws/test-cdylib#1.1.0"),
+        "test-cdylib"
+    );
+    assert_eq!(
+        package_name("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0"),
+        "serde"
+    );
+    assert_eq!(
+        package_name("test-cdylib 1.1.0 (path+file:///ws/test-cdylib)"),
+        "test-cdylib"
+    );
+}
@@ -1,7 +1,8 @@
 use crate::error::Error;
+use crate::inherit;
 use crate::manifest::Edition;
 use serde::de::value::MapAccessDeserializer;
-use serde::de::{self, Visitor};
+use serde::de::{self, DeserializeOwned, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap as Map;
 use std::fmt;
@@ -10,37 +11,52 @@ use std::path::Path;
 use std::path::PathBuf;
 use toml::Value;
 
-pub fn get_manifest(manifest_dir: &Path) -> Manifest {
-    try_get_manifest(manifest_dir).unwrap_or_default()
-}
-
-fn try_get_manifest(manifest_dir: &Path) -> Result<Manifest, Error> {
+pub fn get_manifest(manifest_dir: &Path, workspace: &WorkspaceManifest) -> Result<Manifest, Error> {
     let cargo_toml_path = manifest_dir.join("Cargo.toml");
-    let manifest_str = fs::read_to_string(cargo_toml_path)?;
-    let mut manifest: Manifest = toml::from_str(&manifest_str)?;
+    let mut manifest: Value = read_manifest(manifest_dir)?;
+    inherit::resolve(&mut manifest, &workspace.workspace, &cargo_toml_path)?;
+    let mut manifest: Manifest = manifest.try_into().map_err(|source| Error::Manifest {
+        path: cargo_toml_path,
+        source,
+    })?;
 
     fix_dependencies(&mut manifest.dependencies, manifest_dir);
     fix_dependencies(&mut manifest.dev_dependencies, manifest_dir);
+    fix_dependencies(&mut manifest.build_dependencies, manifest_dir);
+    for target in manifest.target.values_mut() {
+        fix_dependencies(&mut target.dependencies, manifest_dir);
+        fix_dependencies(&mut target.dev_dependencies, manifest_dir);
+        fix_dependencies(&mut target.build_dependencies, manifest_dir);
+    }
 
     Ok(manifest)
 }
 
-pub fn get_workspace_manifest(manifest_dir: &Path) -> WorkspaceManifest {
-    try_get_workspace_manifest(manifest_dir).unwrap_or_default()
-}
-
-pub fn try_get_workspace_manifest(manifest_dir: &Path) -> Result<WorkspaceManifest, Error> {
-    let cargo_toml_path = manifest_dir.join("Cargo.toml");
-    let manifest_str = fs::read_to_string(cargo_toml_path)?;
-    let mut manifest: WorkspaceManifest = toml::from_str(&manifest_str)?;
+pub fn get_workspace_manifest(manifest_dir: &Path) -> Result<WorkspaceManifest, Error> {
+    let mut manifest: WorkspaceManifest = read_manifest(manifest_dir)?;
 
     fix_patches(&mut manifest.patch, manifest_dir);
     fix_replacements(&mut manifest.replace, manifest_dir);
+    // Unlike a package's own dependencies, test-cdylib is kept here as members
+    // may still inherit it.
+    for dep in manifest.workspace.dependencies.values_mut() {
+        dep.path = dep.path.as_ref().map(|path| manifest_dir.join(path));
+    }
 
     Ok(manifest)
 }
 
-fn fix_dependencies(dependencies: &mut Map<String, Dependency>, dir: &Path) {
+fn read_manifest<T: DeserializeOwned>(manifest_dir: &Path) -> Result<T, Error> {
+    let cargo_toml_path = manifest_dir.join("Cargo.toml");
+    let manifest_str = fs::read_to_string(&cargo_toml_path)
+        .map_err(|err| Error::Open(cargo_toml_path.clone(), err))?;
+    toml::from_str(&manifest_str).map_err(|source| Error::Manifest {
+        path: cargo_toml_path,
+        source,
+    })
+}
+
+pub fn fix_dependencies(dependencies: &mut Map<String, Dependency>, dir: &Path) {
     dependencies.remove("test-cdylib");
     for dep in dependencies.values_mut() {
         dep.path = dep.path.as_ref().map(|path| dir.join(path));
@@ -69,6 +85,22 @@ pub struct WorkspaceManifest {
     pub patch: Map<String, RegistryPatch>,
     #[serde(default)]
     pub replace: Map<String, Patch>,
+    #[serde(default)]
+    pub profile: Map<String, Value>,
+    #[serde(default)]
+    pub workspace: WorkspaceTable,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct WorkspaceTable {
+    #[serde(default)]
+    pub resolver: Option<String>,
+    #[serde(default)]
+    pub package: Map<String, Value>,
+    #[serde(default)]
+    pub dependencies: Map<String, Dependency>,
+    #[serde(default)]
+    pub lints: Option<Value>,
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -81,12 +113,32 @@ pub struct Manifest {
     pub dependencies: Map<String, Dependency>,
     #[serde(default, alias = "dev-dependencies")]
     pub dev_dependencies: Map<String, Dependency>,
+    #[serde(default, alias = "build-dependencies")]
+    pub build_dependencies: Map<String, Dependency>,
+    #[serde(default)]
+    pub target: Map<String, Target>,
+    #[serde(default)]
+    pub lints: Option<Value>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct Target {
+    #[serde(default)]
+    pub dependencies: Map<String, Dependency>,
+    #[serde(default, alias = "dev-dependencies")]
+    pub dev_dependencies: Map<String, Dependency>,
+    #[serde(default, alias = "build-dependencies")]
+    pub build_dependencies: Map<String, Dependency>,
 }
 
 #[derive(Deserialize, Default, Debug)]
 pub struct Package {
     #[serde(default)]
     pub edition: Edition,
+    #[serde(default, rename = "rust-version")]
+    pub rust_version: Option<String>,
+    #[serde(default)]
+    pub resolver: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -104,6 +156,8 @@ pub struct Dependency {
     pub default_features: bool,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
     #[serde(flatten)]
     pub rest: Map<String, Value>,
 }
@@ -133,6 +187,11 @@ fn is_true(boolean: &bool) -> bool {
     *boolean
 }
 
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(boolean: &bool) -> bool {
+    !*boolean
+}
+
 impl Serialize for Dependency {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -168,6 +227,7 @@ impl<'de> Deserialize<'de> for Dependency {
                     path: None,
                     default_features: true,
                     features: Vec::new(),
+                    optional: false,
                     rest: Map::new(),
                 })
             }
@@ -183,3 +243,59 @@ impl<'de> Deserialize<'de> for Dependency {
         deserializer.deserialize_any(DependencyVisitor)
     }
 }
+
+#[test]
+fn test_modern_manifest() {
+    let manifest: Manifest = toml::from_str(
+        r#"
+        [package]
+        name = "modern"
+        version = "0.1.0"
+        edition = "2024"
+        rust-version = "1.85"
+        resolver = "3"
+
+        [dependencies]
+        serde = "1.0"
+
+        [lints.rust]
+        unsafe_code = "forbid"
+        "#,
+    )
+    .unwrap();
+
+    assert!(matches!(manifest.package.edition, Edition::E2024));
+    assert_eq!(manifest.package.rust_version.as_deref(), Some("1.85"));
+    assert_eq!(manifest.package.resolver.as_deref(), Some("3"));
+    assert!(manifest.dependencies.contains_key("serde"));
+    assert!(manifest.lints.is_some());
+}
+
+#[test]
+fn test_target_dependencies() {
+    let dir = std::env::temp_dir().join("test-cdylib-target-dependencies");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        r#"
+        [package]
+        name = "targets"
+
+        [target.'cfg(unix)'.dependencies]
+        libc = "0.2"
+
+        [target.'cfg(windows)'.dev-dependencies]
+        helper = { path = "helper" }
+        "#,
+    )
+    .unwrap();
+
+    let manifest = get_manifest(&dir, &WorkspaceManifest::default()).unwrap();
+    let unix = &manifest.target["cfg(unix)"];
+    assert_eq!(unix.dependencies["libc"].version.as_deref(), Some("0.2"));
+    let windows = &manifest.target["cfg(windows)"];
+    assert_eq!(
+        windows.dev_dependencies["helper"].path,
+        Some(dir.join("helper"))
+    );
+}
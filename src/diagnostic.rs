@@ -0,0 +1,84 @@
+use cargo_metadata::diagnostic::{self, DiagnosticLevel};
+use std::path::PathBuf;
+
+/// A message the compiler emitted while building a cdylib, e.g. a warning.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Diagnostic {
+    /// The severity, e.g. `"error"` or `"warning"`.
+    pub level: String,
+    /// The lint or error code, e.g. `"improper_ctypes_definitions"` or
+    /// `"E0308"`.
+    pub code: Option<String>,
+    /// The main message.
+    pub message: String,
+    /// The locations in the source the message refers to.
+    pub spans: Vec<DiagnosticSpan>,
+    /// The message as rustc would print it.
+    pub rendered: Option<String>,
+}
+
+/// A location in the source a [`Diagnostic`] refers to.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DiagnosticSpan {
+    /// The file, relative to the package that was built.
+    pub file_name: PathBuf,
+    /// The first line, starting at 1.
+    pub line_start: usize,
+    /// The last line, starting at 1.
+    pub line_end: usize,
+    /// The first column, starting at 1.
+    pub column_start: usize,
+    /// The column after the span, starting at 1.
+    pub column_end: usize,
+    /// Whether this is the main location of the message.
+    pub is_primary: bool,
+    /// The label shown next to the span, if any.
+    pub label: Option<String>,
+}
+
+impl Diagnostic {
+    /// Whether this is a warning.
+    pub fn is_warning(&self) -> bool {
+        self.level == "warning"
+    }
+
+    /// Whether this is an error.
+    pub fn is_error(&self) -> bool {
+        self.level == "error" || self.level == "error: internal compiler error"
+    }
+}
+
+impl From<diagnostic::Diagnostic> for Diagnostic {
+    fn from(diagnostic: diagnostic::Diagnostic) -> Self {
+        let level = match diagnostic.level {
+            DiagnosticLevel::Ice => "error: internal compiler error",
+            DiagnosticLevel::Error => "error",
+            DiagnosticLevel::Warning => "warning",
+            DiagnosticLevel::FailureNote => "failure-note",
+            DiagnosticLevel::Note => "note",
+            DiagnosticLevel::Help => "help",
+            _ => "unknown",
+        };
+        Diagnostic {
+            level: level.to_owned(),
+            code: diagnostic.code.map(|code| code.code),
+            message: diagnostic.message,
+            spans: diagnostic
+                .spans
+                .into_iter()
+                .map(|span| DiagnosticSpan {
+                    file_name: span.file_name.into(),
+                    line_start: span.line_start,
+                    line_end: span.line_end,
+                    column_start: span.column_start,
+                    column_end: span.column_end,
+                    is_primary: span.is_primary,
+                    label: span.label,
+                })
+                .collect(),
+            rendered: diagnostic.rendered,
+        }
+    }
+}
@@ -1,23 +1,146 @@
 use std::env;
+use std::error::Error as StdError;
 use std::fmt::{self, Display};
 use std::io;
 use std::path::PathBuf;
+use std::process::ExitStatus;
 
+use crate::diagnostic::Diagnostic;
+use crate::snapshot;
+
+/// An error that occurred while building a cdylib.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    Cargo(io::Error),
-    CargoFail,
+    /// Cargo could not be executed.
+    CargoMissing {
+        /// The command line that failed to start.
+        command: String,
+        /// The error returned when spawning cargo.
+        source: io::Error,
+    },
+    /// Rustc could not be executed to find the host triple.
+    RustcMissing {
+        /// The command line that failed to start.
+        command: String,
+        /// The error returned when spawning rustc.
+        source: io::Error,
+    },
+    /// The output of `rustc -vV` didn't include the host triple.
+    HostTriple {
+        /// The rustc command line.
+        command: String,
+        /// The output of rustc.
+        output: String,
+    },
+    /// Cargo exited unsuccessfully, e.g. because the cdylib failed to compile.
+    CompileError {
+        /// The cargo command line.
+        command: String,
+        /// The exit status of cargo.
+        status: ExitStatus,
+        /// The captured stderr of cargo.
+        stderr: String,
+        /// The diagnostics emitted by the compiler.
+        diagnostics: Vec<Diagnostic>,
+        /// The generated project directory, if the build used one.
+        project_dir: Option<PathBuf>,
+    },
+    /// Cargo succeeded but did not produce a cdylib.
+    CdylibNotFound {
+        /// The cargo command line.
+        command: String,
+        /// The exit status of cargo.
+        status: ExitStatus,
+        /// The captured stderr of cargo.
+        stderr: String,
+        /// The diagnostics emitted by the compiler.
+        diagnostics: Vec<Diagnostic>,
+        /// The generated project directory, if the build used one.
+        project_dir: Option<PathBuf>,
+    },
+    /// The cdylib was built, but with warnings, which were denied by
+    /// [`CdylibBuilder::deny_warnings`](crate::CdylibBuilder::deny_warnings).
+    Warnings {
+        /// The cargo command line.
+        command: String,
+        /// The diagnostics emitted by the compiler, including the warnings.
+        diagnostics: Vec<Diagnostic>,
+        /// The generated project directory, if the build used one.
+        project_dir: Option<PathBuf>,
+    },
+    /// A build expected to fail succeeded.
+    UnexpectedSuccess {
+        /// The path to the compiled cdylib.
+        artifact: PathBuf,
+    },
+    /// The output of a build doesn't match its snapshot.
+    SnapshotMismatch {
+        /// The path to the snapshot.
+        path: PathBuf,
+        /// The contents of the snapshot, if it exists.
+        expected: Option<String>,
+        /// The actual output.
+        actual: String,
+    },
+    /// `cargo metadata` failed or returned unreadable output.
+    Metadata {
+        /// The cargo command line.
+        command: String,
+        /// The captured stderr of cargo.
+        stderr: String,
+        /// The error encountered while parsing the output.
+        source: serde_json::Error,
+    },
+    /// A cdylib could not be parsed to read its exported symbols.
+    Object {
+        /// The path to the cdylib.
+        path: PathBuf,
+        /// The error encountered while parsing it.
+        source: object::read::Error,
+    },
+    /// An I/O error.
     Io(io::Error),
-    Metadata(serde_json::Error),
+    /// The given file could not be opened.
     Open(PathBuf, io::Error),
+    /// `CARGO_PKG_NAME` was not set.
     PkgName(env::VarError),
+    /// `CARGO_MANIFEST_DIR` was not set.
     ProjectDir,
+    /// A `Cargo.toml` could not be parsed.
+    Manifest {
+        /// The path to the manifest.
+        path: PathBuf,
+        /// The error encountered while parsing it.
+        source: toml::de::Error,
+    },
+    /// A `Cargo.toml` inherits a key that the workspace root doesn't define.
+    Inherit {
+        /// The path to the manifest.
+        path: PathBuf,
+        /// The inherited key, e.g. `package.edition`.
+        key: String,
+    },
+    /// No package of the workspace has the given name.
+    PackageNotFound {
+        /// The name of the package.
+        name: String,
+    },
+    /// A manifest overlay sets a key the generated manifest already sets to a
+    /// different value.
+    ManifestConflict {
+        /// The conflicting key, e.g. `lib.name`.
+        key: String,
+    },
+    /// A manifest could not be parsed.
     TomlDe(toml::de::Error),
+    /// A manifest could not be written.
     TomlSer(toml::ser::Error),
+    /// Cargo's JSON output could not be parsed.
     Json(serde_json::Error),
-    CdylibNotFound,
 }
 
+/// A specialized `Result` type for building cdylibs.
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl Display for Error {
@@ -25,19 +148,116 @@ impl Display for Error {
         use self::Error::*;
 
         match self {
-            Cargo(e) => write!(f, "failed to execute cargo: {}", e),
-            CargoFail => write!(f, "cargo reported an error"),
+            CargoMissing { command, source } => {
+                write!(f, "failed to execute cargo (`{}`): {}", command, source)
+            }
+            RustcMissing { command, source } => {
+                write!(f, "failed to execute rustc (`{}`): {}", command, source)
+            }
+            HostTriple { command, .. } => {
+                write!(f, "failed to find the host triple in the output of `{}`", command)
+            }
+            CompileError {
+                command, status, ..
+            } => write!(f, "cargo reported an error (`{}`, {})", command, status),
+            CdylibNotFound { .. } => {
+                write!(f, "can't find cdylib(.dll,.so,.cdylib) in output dir, please check that you have set [crate-type] correctly in Cargo.toml")
+            }
+            Warnings {
+                command,
+                diagnostics,
+                ..
+            } => {
+                let warnings = diagnostics.iter().filter(|d| d.is_warning()).count();
+                write!(f, "cargo reported {} denied warning(s) (`{}`)", warnings, command)
+            }
+            UnexpectedSuccess { artifact } => write!(
+                f,
+                "expected the build to fail, but it built {}",
+                artifact.display()
+            ),
+            SnapshotMismatch {
+                path,
+                expected: None,
+                actual,
+            } => write!(
+                f,
+                "{} doesn't exist, set TEST_CDYLIB=overwrite to create it with:\n{}",
+                path.display(),
+                actual
+            ),
+            SnapshotMismatch {
+                path,
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "{} doesn't match, set TEST_CDYLIB=overwrite to update it:\n{}",
+                path.display(),
+                snapshot::diff(expected, actual)
+            ),
+            Metadata {
+                command, source, ..
+            } => write!(
+                f,
+                "failed to read cargo metadata (`{}`): {}",
+                command, source
+            ),
+            Manifest { path, source } => {
+                write!(f, "failed to parse {}: {}", path.display(), source)
+            }
+            Inherit { path, key } => write!(
+                f,
+                "{}: `{}` is inherited from the workspace, but the workspace root doesn't define it",
+                path.display(),
+                key
+            ),
+            PackageNotFound { name } => {
+                write!(f, "the workspace has no package named `{}`", name)
+            }
+            ManifestConflict { key } => write!(
+                f,
+                "manifest overlay conflicts with the generated value of `{}`",
+                key
+            ),
+            Object { path, source } => {
+                write!(f, "failed to read symbols of {}: {}", path.display(), source)
+            }
             Io(e) => e.fmt(f),
-            Metadata(e) => write!(f, "failed to read cargo metadata: {}", e),
             Open(path, e) => write!(f, "{}: {}", path.display(), e),
             PkgName(e) => write!(f, "failed to detect CARGO_PKG_NAME: {}", e),
             ProjectDir => write!(f, "failed to determine name of project dir"),
             TomlDe(e) => e.fmt(f),
             TomlSer(e) => e.fmt(f),
             Json(e) => e.fmt(f),
-            CdylibNotFound => {
-                write!(f, "can't find cdylib(.dll,.so,.cdylib) in output dir, please check that you have set [crate-type] correctly in Cargo.toml")
-            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::Error::*;
+
+        match self {
+            CargoMissing { source, .. } | RustcMissing { source, .. } => Some(source),
+            Metadata { source, .. } => Some(source),
+            Manifest { source, .. } => Some(source),
+            Object { source, .. } => Some(source),
+            Io(e) | Open(_, e) => Some(e),
+            PkgName(e) => Some(e),
+            TomlDe(e) => Some(e),
+            TomlSer(e) => Some(e),
+            Json(e) => Some(e),
+            CompileError { .. }
+            | HostTriple { .. }
+            | Warnings { .. }
+            | UnexpectedSuccess { .. }
+            | SnapshotMismatch { .. }
+            | CdylibNotFound { .. }
+            | Inherit { .. }
+            | PackageNotFound { .. }
+            | ManifestConflict { .. }
+            | ProjectDir => None,
         }
     }
 }
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap as Map;
+
+use crate::dependencies::{self, Dependency};
+use crate::manifest::Manifest;
+
+/// Returns the features of a package, including the implicit feature of each
+/// optional dependency that no feature refers to with `dep:`.
+pub fn all_features(manifest: &dependencies::Manifest) -> Map<String, Vec<String>> {
+    let mut features = manifest.features.clone();
+    let hidden: Vec<&str> = manifest
+        .features
+        .values()
+        .flatten()
+        .filter_map(|value| value.strip_prefix("dep:"))
+        .collect();
+
+    let mut tables = vec![&manifest.dependencies, &manifest.build_dependencies];
+    for target in manifest.target.values() {
+        tables.extend(vec![&target.dependencies, &target.build_dependencies]);
+    }
+    for (name, dependency) in tables.into_iter().flatten() {
+        if dependency.optional && !hidden.contains(&name.as_str()) {
+            features
+                .entry(name.clone())
+                .or_insert_with(|| vec![format!("dep:{}", name)]);
+        }
+    }
+    features
+}
+
+/// Translates the features of the crate under test into those of a fixture
+/// depending on it.
+///
+/// Each feature enables the crate's feature of the same name, along with the
+/// fixture's own copies of the dependencies it enables. Edges to
+/// dependencies the fixture doesn't have are dropped.
+pub fn translate(
+    crate_name: &str,
+    features: &Map<String, Vec<String>>,
+    fixture: &Manifest,
+) -> Map<String, Vec<String>> {
+    let is_optional = |name: &str| optional(fixture, name);
+    features
+        .iter()
+        .map(|(feature, values)| {
+            let enable = format!("{}/{}", crate_name, feature);
+            let edges = values
+                .iter()
+                .filter_map(|value| translate_value(value, &is_optional));
+            (
+                feature.clone(),
+                Some(enable).into_iter().chain(edges).collect(),
+            )
+        })
+        .collect()
+}
+
+fn translate_value(value: &str, is_optional: &dyn Fn(&str) -> Option<bool>) -> Option<String> {
+    if let Some(name) = value.strip_prefix("dep:") {
+        // A required dependency is always enabled.
+        return match is_optional(name) {
+            Some(true) => Some(value.to_owned()),
+            _ => None,
+        };
+    }
+    match value.split_once('/') {
+        Some((name, feature)) => {
+            let name = name.strip_suffix('?').unwrap_or(name);
+            match is_optional(name)? {
+                true => Some(value.to_owned()),
+                // `?` is only allowed on optional dependencies.
+                false => Some(format!("{}/{}", name, feature)),
+            }
+        }
+        // Every feature of the crate is also a feature of the fixture.
+        None => Some(value.to_owned()),
+    }
+}
+
+/// Returns whether the fixture's dependency of the given name is optional,
+/// or `None` if it doesn't have one.
+fn optional(fixture: &Manifest, name: &str) -> Option<bool> {
+    let mut tables = vec![&fixture.dependencies, &fixture.build_dependencies];
+    for target in fixture.target.values() {
+        tables.extend(vec![&target.dependencies, &target.build_dependencies]);
+    }
+    let mut found: Vec<&Dependency> = tables
+        .into_iter()
+        .filter_map(|table| table.get(name))
+        .collect();
+    found.sort_by_key(|dependency| dependency.optional);
+    // Any required entry makes the dependency required.
+    found.first().map(|dependency| dependency.optional)
+}
+
+#[test]
+fn test_translate() {
+    let source: dependencies::Manifest = toml::from_str(
+        r#"
+        [features]
+        default = ["std"]
+        std = ["serde?/std", "log/std"]
+        ffi = ["dep:libc", "serde_json/std"]
+
+        [dependencies]
+        serde = { version = "1.0", optional = true }
+        libc = { version = "0.2", optional = true }
+        log = { version = "0.4", optional = true }
+        serde_json = { version = "1.0", optional = true }
+        "#,
+    )
+    .unwrap();
+    let features = all_features(&source);
+    assert_eq!(features["serde"], ["dep:serde"]);
+    assert!(!features.contains_key("libc"));
+
+    let dependencies: dependencies::Manifest = toml::from_str(
+        r#"
+        [dependencies]
+        serde = { version = "1.0", optional = true }
+        libc = "0.2"
+        "#,
+    )
+    .unwrap();
+    let mut fixture = Manifest {
+        package: crate::manifest::Package {
+            name: "fixture".to_owned(),
+            version: "0.0.0".to_owned(),
+            edition: Default::default(),
+            build: None,
+            rust_version: None,
+            publish: false,
+        },
+        features: Map::new(),
+        dependencies: dependencies.dependencies,
+        build_dependencies: Map::new(),
+        target: Map::new(),
+        lib: crate::manifest::Lib::new("fixture.rs".into()),
+        lints: None,
+    };
+    fixture.features = translate("krate", &features, &fixture);
+    assert_eq!(fixture.features["default"], ["krate/default", "std"]);
+    assert_eq!(fixture.features["std"], ["krate/std", "serde?/std"]);
+    assert_eq!(fixture.features["ffi"], ["krate/ffi"]);
+    assert_eq!(fixture.features["serde"], ["krate/serde", "dep:serde"]);
+    assert_eq!(fixture.features["log"], ["krate/log"]);
+}
@@ -1,21 +1,45 @@
 use serde::de::DeserializeOwned;
 use serde::{de, Deserialize, Deserializer};
 use std::env;
-use std::error::Error;
 use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-pub fn find() -> Option<Vec<String>> {
-    try_find().ok()
-}
+use crate::cargo;
 
-struct Ignored;
+/// The features of the running test as a comma separated list, either set by
+/// [`export`] or by hand.
+const FEATURES_VAR: &str = "TEST_CDYLIB_FEATURES";
 
-impl<E: Error> From<E> for Ignored {
-    fn from(_error: E) -> Self {
-        Ignored
-    }
+/// Makes cargo set [`FEATURES_VAR`] to the features the package is built
+/// with when it runs the package's tests. Called from its build script.
+pub fn export() {
+    // Setting the variable by hand still overrides the features.
+    println!("cargo:rerun-if-env-changed={}", FEATURES_VAR);
+    let features = env::var(FEATURES_VAR)
+        .or_else(|_| env::var("CARGO_CFG_FEATURE"))
+        .unwrap_or_default();
+    println!("cargo:rustc-env={}={}", FEATURES_VAR, features);
+}
+
+/// Returns the features the running test was built with, or `None` after
+/// warning if they can't be determined.
+pub fn find() -> Option<Vec<String>> {
+    static FEATURES: OnceLock<Option<Vec<String>>> = OnceLock::new();
+    FEATURES
+        .get_or_init(|| match try_find() {
+            Ok(features) => Some(features),
+            Err(reason) => {
+                eprintln!(
+                    "warning: test-cdylib couldn't detect the features of the running test \
+                     ({}), so the default features are used. Set {} to select them.",
+                    reason, FEATURES_VAR,
+                );
+                None
+            }
+        })
+        .clone()
 }
 
 #[derive(Deserialize)]
@@ -24,74 +48,173 @@ struct Build {
     features: Vec<String>,
 }
 
-fn try_find() -> Result<Vec<String>, Ignored> {
-    // This will look something like:
-    //   /path/to/crate_name/target/debug/deps/test_name-HASH
-    let test_binary = env::args_os().next().ok_or(Ignored)?;
-
-    // The hash at the end is ascii so not lossy, rest of conversion doesn't
-    // matter.
-    let test_binary_lossy = test_binary.to_string_lossy();
-    let hash_range = if cfg!(windows) {
-        // Trim ".exe" from the binary name for windows.
-        test_binary_lossy.len() - 21..test_binary_lossy.len() - 4
-    } else {
-        test_binary_lossy.len() - 17..test_binary_lossy.len()
-    };
-    let hash = test_binary_lossy.get(hash_range).ok_or(Ignored)?;
-    if !hash.starts_with('-') || !hash[1..].bytes().all(is_lower_hex_digit) {
-        return Err(Ignored);
+fn try_find() -> Result<Vec<String>, String> {
+    if let Some(features) = env::var_os(FEATURES_VAR) {
+        return Ok(parse_list(&features.to_string_lossy()));
+    }
+
+    let test_binary = env::current_exe().map_err(|err| err.to_string())?;
+    let mut build_dirs = Vec::new();
+    for var in [
+        "CARGO_BUILD_BUILD_DIR",
+        "CARGO_BUILD_TARGET_DIR",
+        "CARGO_TARGET_DIR",
+    ] {
+        if let Some(dir) = env::var_os(var) {
+            build_dirs.push(expand_build_dir(
+                &dir.to_string_lossy(),
+                build_dir_template,
+            )?);
+        }
     }
+    find_in_fingerprints(&test_binary, &build_dirs)
+}
 
-    let binary_path = PathBuf::from(&test_binary);
+fn parse_list(features: &str) -> Vec<String> {
+    features
+        .split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Reads the features of the test binary from the fingerprint cargo saved
+/// for it, in one of the binary's ancestors or one of the given build dirs.
+fn find_in_fingerprints(test_binary: &Path, build_dirs: &[PathBuf]) -> Result<Vec<String>, String> {
+    // This will look something like:
+    //   /path/to/crate_name/target/debug/deps/test_name-HASH
+    let hash = test_binary
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .and_then(binary_hash)
+        .ok_or_else(|| format!("{} has no hash suffix", test_binary.display()))?;
 
     // Feature selection is saved in:
     //   /path/to/crate_name/target/debug/.fingerprint/*-HASH/*-HASH.json
-    let up = binary_path
-        .parent()
-        .ok_or(Ignored)?
-        .parent()
-        .ok_or(Ignored)?;
-    let fingerprint_dir = up.join(".fingerprint");
-    if !fingerprint_dir.is_dir() {
-        return Err(Ignored);
+    // The fingerprints may also be in a separate build directory, or a
+    // target specific one when building with `--target`.
+    let suffix = format!("-{}", hash);
+    let fingerprint = fingerprint_dirs(test_binary, build_dirs)
+        .into_iter()
+        .find_map(|dir| find_entry(&dir, |name, is_dir| is_dir && name.ends_with(&suffix)))
+        .ok_or_else(|| format!("no fingerprint found for {}", test_binary.display()))?;
+    let json = find_entry(&fingerprint, |name, is_dir| {
+        !is_dir && Path::new(name).extension() == Some(OsStr::new("json"))
+    })
+    .ok_or_else(|| format!("no fingerprint in {}", fingerprint.display()))?;
+
+    let build_json = fs::read_to_string(&json).map_err(|err| err.to_string())?;
+    let build: Build = serde_json::from_str(&build_json).map_err(|err| err.to_string())?;
+    Ok(build.features)
+}
+
+/// Expands the `{name}` templates cargo allows in `build.build-dir`, failing
+/// for those `lookup` can't resolve.
+fn expand_build_dir(
+    dir: &str,
+    lookup: impl Fn(&str) -> Option<PathBuf>,
+) -> Result<PathBuf, String> {
+    let mut expanded = String::new();
+    let mut rest = dir;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("unterminated template in build dir {}", dir))?;
+        let name = &rest[start + 1..end];
+        let value = lookup(name)
+            .ok_or_else(|| format!("can't expand `{{{}}}` in build dir {}", name, dir))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value.to_string_lossy());
+        rest = &rest[end + 1..];
     }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
 
-    let mut hash_matches = Vec::new();
-    for entry in fingerprint_dir.read_dir()? {
-        let entry = entry?;
-        let is_dir = entry.file_type()?.is_dir();
-        let matching_hash = entry.file_name().to_string_lossy().ends_with(hash);
-        if is_dir && matching_hash {
-            hash_matches.push(entry.path());
-        }
+/// Resolves a `build.build-dir` template. `{workspace-path-hash}` is derived
+/// by cargo in a way that isn't stable, so it isn't supported.
+fn build_dir_template(name: &str) -> Option<PathBuf> {
+    match name {
+        "workspace-root" => cargo::metadata()
+            .ok()
+            .map(|metadata| metadata.workspace_root),
+        "cargo-cache-home" => env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".cargo"))),
+        _ => None,
     }
+}
 
-    if hash_matches.len() != 1 {
-        return Err(Ignored);
+/// Returns the hash cargo appends to the name of a test binary.
+fn binary_hash(file_stem: &str) -> Option<&str> {
+    let (_, hash) = file_stem.rsplit_once('-')?;
+    if hash.len() == 16 && hash.bytes().all(is_lower_hex_digit) {
+        Some(hash)
+    } else {
+        None
     }
+}
+
+/// The `.fingerprint` directories that may belong to the test binary, the
+/// closest first.
+fn fingerprint_dirs(test_binary: &Path, build_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = test_binary
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(".fingerprint"))
+        .collect();
 
-    let mut json_matches = Vec::new();
-    for entry in hash_matches[0].read_dir()? {
-        let entry = entry?;
-        let is_file = entry.file_type()?.is_file();
-        let is_json = entry.path().extension() == Some(OsStr::new("json"));
-        if is_file && is_json {
-            json_matches.push(entry.path());
+    // Below these, the fingerprints are in `<profile>/.fingerprint` or
+    // `<triple>/<profile>/.fingerprint`.
+    for build_dir in build_dirs {
+        for profile in subdirs(build_dir) {
+            dirs.push(profile.join(".fingerprint"));
+            for profile in subdirs(&profile) {
+                dirs.push(profile.join(".fingerprint"));
+            }
         }
     }
 
-    if json_matches.len() != 1 {
-        return Err(Ignored);
-    }
+    dirs.retain(|dir| dir.is_dir());
+    dirs
+}
 
-    let build_json = fs::read_to_string(&json_matches[0])?;
-    let build: Build = serde_json::from_str(&build_json)?;
-    Ok(build.features)
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Returns the only entry of `dir` matching `filter`, which is given its name
+/// and whether it's a directory.
+fn find_entry(dir: &Path, filter: impl Fn(&str, bool) -> bool) -> Option<PathBuf> {
+    let mut matches = dir.read_dir().ok()?.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let is_dir = entry.file_type().ok()?.is_dir();
+        let name = entry.file_name();
+        if filter(&name.to_string_lossy(), is_dir) {
+            Some(entry.path())
+        } else {
+            None
+        }
+    });
+    let found = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(found)
 }
 
 fn is_lower_hex_digit(byte: u8) -> bool {
-    byte >= b'0' && byte <= b'9' || byte >= b'a' && byte <= b'f'
+    byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte)
 }
 
 fn from_json<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -102,3 +225,86 @@ where
     let json = String::deserialize(deserializer)?;
     serde_json::from_str(&json).map_err(de::Error::custom)
 }
+
+#[test]
+fn test_binary_hash() {
+    assert_eq!(
+        binary_hash("load_lib-0123456789abcdef"),
+        Some("0123456789abcdef")
+    );
+    assert_eq!(binary_hash("load-lib"), None);
+    assert_eq!(binary_hash("load_lib-0123456789ABCDEF"), None);
+    assert_eq!(binary_hash("load_lib"), None);
+}
+
+#[cfg(test)]
+fn write_fingerprint(dir: &Path, hash: &str, features: &str) {
+    let fingerprint = dir.join(format!(".fingerprint/test-cdylib-{}", hash));
+    fs::create_dir_all(&fingerprint).unwrap();
+    let build = serde_json::json!({ "features": features });
+    fs::write(
+        fingerprint.join("test-integration-test-load_lib.json"),
+        build.to_string(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_parse_list() {
+    assert_eq!(parse_list(" a, b-c,,d "), ["a", "b-c", "d"]);
+    assert!(parse_list("").is_empty());
+}
+
+#[test]
+fn test_find_in_fingerprints() {
+    let root = env::temp_dir().join(format!("test-cdylib-features-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    // `cargo test --target <triple>` puts the binary and its fingerprint
+    // below a target specific directory.
+    let profile = root.join("target/x86_64-unknown-linux-gnu/debug");
+    write_fingerprint(&profile, "0123456789abcdef", r#"["a","b"]"#);
+    let test_binary = profile.join("deps/load_lib-0123456789abcdef");
+    assert_eq!(find_in_fingerprints(&test_binary, &[]).unwrap(), ["a", "b"]);
+
+    // A separate build dir only holds the fingerprint.
+    let build_dirs = vec![root.join("build")];
+    write_fingerprint(&build_dirs[0].join("debug"), "fedcba9876543210", r#"["c"]"#);
+    let test_binary = root.join("target/debug/deps/load_lib-fedcba9876543210");
+    assert!(find_in_fingerprints(&test_binary, &[]).is_err());
+    assert_eq!(
+        find_in_fingerprints(&test_binary, &build_dirs).unwrap(),
+        ["c"]
+    );
+
+    // Or below a target specific directory too.
+    write_fingerprint(
+        &build_dirs[0].join("x86_64-unknown-linux-gnu/release"),
+        "00112233445566ff",
+        "[]",
+    );
+    let test_binary = root.join("target/release/deps/load_lib-00112233445566ff");
+    assert!(find_in_fingerprints(&test_binary, &build_dirs)
+        .unwrap()
+        .is_empty());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_expand_build_dir() {
+    let lookup = |name: &str| match name {
+        "workspace-root" => Some(PathBuf::from("/ws")),
+        _ => None,
+    };
+    assert_eq!(
+        expand_build_dir("{workspace-root}/build", lookup).unwrap(),
+        Path::new("/ws/build")
+    );
+    assert_eq!(
+        expand_build_dir("/tmp/build", lookup).unwrap(),
+        Path::new("/tmp/build")
+    );
+    assert!(expand_build_dir("/tmp/{workspace-path-hash}", lookup).is_err());
+    assert!(expand_build_dir("/tmp/{workspace-root", lookup).is_err());
+}
@@ -0,0 +1,127 @@
+use serde::de::Error as _;
+use serde::Deserialize;
+use std::collections::BTreeMap as Map;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+use crate::dependencies::{self, Dependency, Target};
+use crate::error::{Error, Result};
+use crate::manifest::Manifest;
+
+/// Manifest entries declared by a fixture itself, in a `cargo` code block of
+/// its leading doc comment:
+///
+/// ```text
+/// //! ```cargo
+/// //! [dependencies]
+/// //! libc = { version = "0.2", features = ["extra_traits"] }
+/// //! ```
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FrontMatter {
+    #[serde(default)]
+    pub dependencies: Map<String, Dependency>,
+    #[serde(default, rename = "build-dependencies")]
+    pub build_dependencies: Map<String, Dependency>,
+    #[serde(default)]
+    pub target: Map<String, Target>,
+    #[serde(default)]
+    pub features: Map<String, Vec<String>>,
+    #[serde(default)]
+    pub lib: Map<String, Value>,
+}
+
+/// Reads the front matter of the given fixture, if it has any.
+pub fn read(path: &Path) -> Result<FrontMatter> {
+    let source = fs::read_to_string(path).map_err(|err| Error::Open(path.to_owned(), err))?;
+    let block = match extract(&source) {
+        Some(block) => block,
+        None => return Ok(FrontMatter::default()),
+    };
+
+    let manifest_error = |source| Error::Manifest {
+        path: path.to_owned(),
+        source,
+    };
+    let mut front_matter: FrontMatter = toml::from_str(&block).map_err(manifest_error)?;
+    for key in &["path", "crate-type", "crate_type"] {
+        if front_matter.lib.contains_key(*key) {
+            let message = format!("`lib.{}` can't be set by a fixture", key);
+            return Err(manifest_error(toml::de::Error::custom(message)));
+        }
+    }
+
+    // Paths are relative to the fixture, like those of a manifest are
+    // relative to its directory.
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    dependencies::fix_dependencies(&mut front_matter.dependencies, dir);
+    dependencies::fix_dependencies(&mut front_matter.build_dependencies, dir);
+    for target in front_matter.target.values_mut() {
+        dependencies::fix_dependencies(&mut target.dependencies, dir);
+        dependencies::fix_dependencies(&mut target.build_dependencies, dir);
+    }
+
+    Ok(front_matter)
+}
+
+/// Returns the contents of the first ```` ```cargo ```` block in the leading
+/// `//!` comment of `source`.
+fn extract(source: &str) -> Option<String> {
+    let mut lines = source
+        .lines()
+        .map(str::trim_start)
+        .skip_while(|line| line.is_empty() || line.starts_with("#!["))
+        .map_while(|line| line.strip_prefix("//!"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line));
+
+    lines.find(|line| line.trim_end() == "```cargo")?;
+    let mut block = String::new();
+    for line in lines {
+        if line.trim_end() == "```" {
+            return Some(block);
+        }
+        block.push_str(line);
+        block.push('\n');
+    }
+    None
+}
+
+impl FrontMatter {
+    /// Merges the front matter into a fixture's manifest, overriding any
+    /// dependencies or features of the same name.
+    pub fn apply(self, manifest: &mut Manifest) {
+        manifest.dependencies.extend(self.dependencies);
+        manifest.build_dependencies.extend(self.build_dependencies);
+        for (cfg, target) in self.target {
+            let fixture_target = manifest.target.entry(cfg).or_default();
+            fixture_target.dependencies.extend(target.dependencies);
+            fixture_target.dependencies.extend(target.dev_dependencies);
+            fixture_target
+                .build_dependencies
+                .extend(target.build_dependencies);
+        }
+        manifest.features.extend(self.features);
+        manifest.lib.rest.extend(self.lib);
+    }
+}
+
+#[test]
+fn test_extract() {
+    let source = "//! A fixture.\n\
+                  //!\n\
+                  //! ```cargo\n\
+                  //! [dependencies]\n\
+                  //! libc = \"0.2\"\n\
+                  //! ```\n\
+                  \n\
+                  //! ```cargo\n\
+                  //! ignored = true\n\
+                  //! ```\n";
+    assert_eq!(
+        extract(source).as_deref(),
+        Some("[dependencies]\nlibc = \"0.2\"\n")
+    );
+    assert_eq!(extract("fn main() {}\n//! ```cargo\n//! ```\n"), None);
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+use toml::value::Table;
+use toml::Value;
+
+use crate::dependencies::WorkspaceTable;
+use crate::error::Error;
+
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// Replaces every `workspace = true` entry of a package manifest with the
+/// value inherited from the workspace root.
+pub fn resolve(manifest: &mut Value, workspace: &WorkspaceTable, path: &Path) -> Result<(), Error> {
+    let missing = |key: String| Error::Inherit {
+        path: path.to_owned(),
+        key,
+    };
+
+    if let Some(Value::Table(package)) = manifest.get_mut("package") {
+        for (key, value) in package.iter_mut() {
+            if is_inherited(value) {
+                *value = workspace
+                    .package
+                    .get(key)
+                    .cloned()
+                    .ok_or_else(|| missing(format!("package.{}", key)))?;
+            }
+        }
+    }
+
+    if let Some(lints) = manifest.get_mut("lints") {
+        if is_inherited(lints) {
+            *lints = workspace
+                .lints
+                .clone()
+                .ok_or_else(|| missing("lints".to_owned()))?;
+        }
+    }
+
+    resolve_dependencies(manifest, "", workspace, &missing)?;
+    if let Some(Value::Table(targets)) = manifest.get_mut("target") {
+        for (cfg, target) in targets.iter_mut() {
+            let prefix = format!("target.{}.", cfg);
+            resolve_dependencies(target, &prefix, workspace, &missing)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_dependencies(
+    tables: &mut Value,
+    prefix: &str,
+    workspace: &WorkspaceTable,
+    missing: &dyn Fn(String) -> Error,
+) -> Result<(), Error> {
+    for &table in DEPENDENCY_TABLES {
+        if let Some(Value::Table(dependencies)) = tables.get_mut(table) {
+            for (name, dependency) in dependencies.iter_mut() {
+                if !is_inherited(dependency) {
+                    continue;
+                }
+                let inherited = workspace
+                    .dependencies
+                    .get(name)
+                    .ok_or_else(|| missing(format!("{}{}.{}", prefix, table, name)))?;
+                let inherited = Value::try_from(inherited)?;
+                merge_dependency(dependency, inherited);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_inherited(value: &Value) -> bool {
+    value.get("workspace").and_then(Value::as_bool) == Some(true)
+}
+
+fn merge_dependency(dependency: &mut Value, inherited: Value) {
+    let mut merged = match inherited {
+        Value::Table(table) => table,
+        version => {
+            let mut table = Table::new();
+            table.insert("version".to_owned(), version);
+            table
+        }
+    };
+
+    if let Value::Table(member) = dependency {
+        for (key, value) in member.iter() {
+            match key.as_str() {
+                // Cargo only lets a member disable default features if the
+                // workspace doesn't enable them, so keep the workspace's.
+                "workspace" | "default-features" | "default_features" => {}
+                // Features are additive.
+                "features" => {
+                    let features = merged
+                        .entry("features")
+                        .or_insert_with(|| Value::Array(Vec::new()));
+                    if let (Value::Array(features), Value::Array(extra)) = (features, value) {
+                        for feature in extra {
+                            if !features.contains(feature) {
+                                features.push(feature.clone());
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    merged.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    *dependency = Value::Table(merged);
+}
+
+#[test]
+fn test_resolve() {
+    use crate::dependencies::WorkspaceManifest;
+
+    let workspace: WorkspaceManifest = toml::from_str(
+        r#"
+        [workspace.package]
+        edition = "2021"
+
+        [workspace.dependencies]
+        serde = { version = "1.0", features = ["derive"] }
+        helper = { path = "helper" }
+
+        [workspace.lints.rust]
+        unsafe_code = "forbid"
+        "#,
+    )
+    .unwrap();
+    let mut manifest: Value = toml::from_str(
+        r#"
+        [package]
+        name = "member"
+        edition.workspace = true
+
+        [dependencies]
+        serde = { workspace = true, features = ["rc"], optional = true }
+
+        [dev-dependencies]
+        helper.workspace = true
+
+        [lints]
+        workspace = true
+        "#,
+    )
+    .unwrap();
+
+    resolve(&mut manifest, &workspace.workspace, Path::new("Cargo.toml")).unwrap();
+
+    assert_eq!(manifest["package"]["edition"].as_str(), Some("2021"));
+    let serde = &manifest["dependencies"]["serde"];
+    assert_eq!(serde["version"].as_str(), Some("1.0"));
+    assert_eq!(serde["optional"].as_bool(), Some(true));
+    assert_eq!(
+        serde["features"],
+        Value::Array(vec!["derive".into(), "rc".into()])
+    );
+    assert!(serde.get("workspace").is_none());
+    assert_eq!(
+        manifest["dev-dependencies"]["helper"]["path"].as_str(),
+        Some("helper")
+    );
+    assert!(manifest["lints"]["rust"].get("unsafe_code").is_some());
+
+    let mut manifest: Value = toml::from_str("[package]\nversion.workspace = true").unwrap();
+    let err = resolve(&mut manifest, &workspace.workspace, Path::new("Cargo.toml")).unwrap_err();
+    assert!(matches!(err, Error::Inherit { key, .. } if key == "package.version"));
+}
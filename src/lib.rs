@@ -18,7 +18,11 @@
 //! ```
 //!
 //! This will build the current project, if it is not already built, and return
-//! the path to the compiled library.
+//! the path to the compiled library. If the project's `crate-type` doesn't
+//! include `cdylib`, [`CdylibBuilder::force_cdylib`] builds it as one anyway.
+//!
+//! Other packages of the workspace, or crates elsewhere on disk, are built the
+//! same way with `build_package("foo-ffi")` and `build_crate_at("../foo-ffi")`.
 //!
 //! ## Testing a cdylib building library
 //!
@@ -44,7 +48,7 @@
 //! ```rust
 //! #[test]
 //! fn api_gen_test() {
-//!     let dylib_path = test_cdylib::build_path("tests/cdylib/api_test.rs");
+//!     let dylib_path = test_cdylib::build_file("tests/cdylib/api_test.rs");
 //!
 //!     // Or load the shared library using any other method of your choice.
 //!     let dylib = dlopen::symbor::Library::open(&dylib_path).unwrap();
@@ -54,50 +58,414 @@
 //! ```
 //!
 //! This will build the given file as a cdylib project, and return the path to
-//! the compiled library. All dependencies and dev-dependencies are available. Note
-//! that this will cause all dependencies to be rebuilt, which can slow down testing
-//! significantly.
+//! the compiled library. The dependencies and dev-dependencies the file refers to,
+//! e.g. with `use serde::Serialize`, are available. Others can be added with
+//! `CdylibBuilder::dependency`, or all of them with
+//! `CdylibBuilder::all_dependencies`. Note that the dependencies are rebuilt
+//! separately from the current project. All files built this way share one target
+//! directory though, so this only happens once.
+//!
+//! ## Fixture dependencies
+//!
+//! A file can declare extra dependencies, features and `[lib]` settings in a
+//! `cargo` block of its leading doc comment, which is merged into the generated
+//! `Cargo.toml`, e.g.
+//!
+//! ```text
+//! //! ```cargo
+//! //! [dependencies]
+//! //! libc = { version = "0.2", features = ["extra_traits"] }
+//! //! ```
+//! ```
+//!
+//! ## Inline libraries
+//!
+//! Small libraries can also be written inline with `build_source`, or the
+//! `cdylib!` macro, which are built the same way as files, e.g.
+//!
+//! ```no_run
+//! let dylib_path = test_cdylib::cdylib! {
+//!     #[no_mangle]
+//!     pub extern "C" fn identity(x: i32) -> i32 {
+//!         x
+//!     }
+//! };
+//! ```
+//!
+//! ## Building many libraries at once
+//!
+//! `build_files` and `build_examples` build several libraries with a single cargo
+//! invocation, and return the result for each of them, e.g.
+//!
+//! ```no_run
+//! let dylibs = test_cdylib::build_files(&["tests/cdylibs/a.rs", "tests/cdylibs/b.rs"]);
+//! for (file, dylib_path) in dylibs {
+//!     assert!(dylib_path.is_ok(), "failed to build {}", file.display());
+//! }
+//! ```
 //!
 //! ## Multiple tests with the same library
 //!
-//! Multiple tests can link to the same library by using
-//! [once_cell](https://crates.io/crates/once_cell) to contain the path to the
-//! library, e.g.
+//! Multiple tests can link to the same library by simply building it in each test.
+//! Builds are only run once per process, later calls wait for the first one and
+//! return the same path. Each configuration of a library, e.g. with other
+//! features, gets a copy of its own, so building one doesn't change the file
+//! another test loaded. Test binaries building the same file at the same time
+//! also wait for each other.
 //!
-//! ```rust
-//! use std::path::PathBuf;
-//! use once_cell::sync::Lazy;
-//! static LIB_PATH: Lazy<PathBuf> = Lazy::new(|| test_cdylib::build_current_project());
+//! ## Configuring a build
+//!
+//! The functions above are shorthands for [`CdylibBuilder`], which allows
+//! choosing features, the target, environment variables, extra rustflags and
+//! cargo arguments, e.g.
+//!
+//! ```no_run
+//! let output = test_cdylib::CdylibBuilder::current_project()
+//!     .features(["extended-api"])
+//!     .rustflag("-Ctarget-cpu=native")
+//!     .build()
+//!     .unwrap();
+//! println!("built {}", output.artifact.display());
 //! ```
 //!
-//! The same can be done for both `build_example` and `build_path`.
+//! Fixtures are built with the features of the running test. Calling
+//! [`export_features`] from the package's build script passes them to the tests
+//! reliably. Otherwise they're read from the comma separated
+//! `TEST_CDYLIB_FEATURES` environment variable if it's set, or from the files
+//! cargo keeps about the test binary, with a warning if that fails. Selecting
+//! them explicitly, e.g. with `no_default_features` or `all_features`,
+//! disables that.
+//! [`CdylibBuilder::build_feature_matrix`] builds the library once for each given
+//! set of features, e.g. to check which symbols each of them exports.
+//!
+//! ## Handling build failures
+//!
+//! Each `build_*` function panics if the build fails. The `try_build_*`
+//! variants return an [`Error`] instead, which can be used to check why a
+//! build failed, e.g.
+//!
+//! ```no_run
+//! let err = test_cdylib::try_build_example("missing").unwrap_err();
+//! assert!(matches!(err, test_cdylib::Error::CompileError { .. }));
+//! ```
+//!
+//! The compiler's diagnostics are returned with the result of a build, so tests
+//! can check for warnings. `CdylibBuilder::quiet` only prints them if the build
+//! fails, and `CdylibBuilder::deny_warnings` turns warnings into an error.
+//!
+//! ## Testing build failures
+//!
+//! `build_file_expect_fail` checks that a file fails to build, and that the
+//! compiler's output matches the `.stderr` file next to it, e.g.
+//! `tests/cdylibs/not_ffi_safe.stderr` for the following. Paths and hashes are
+//! normalized, so that the output doesn't depend on the machine. Run the tests
+//! with `TEST_CDYLIB=overwrite` to write the `.stderr` files.
+//!
+//! ```no_run
+//! test_cdylib::build_file_expect_fail("tests/cdylibs/not_ffi_safe.rs");
+//! ```
+//!
+//! ## Inspecting exported symbols
+//!
+//! [`exported_symbols`] reads the symbols a cdylib exports without loading it,
+//! e.g. to check that a function is exported, or that nothing else is.
+//!
+//! ```no_run
+//! let dylib_path = test_cdylib::build_file("tests/cdylibs/identity.rs");
+//! let symbols = test_cdylib::exported_symbols(&dylib_path).unwrap();
+//! assert!(symbols.iter().any(|symbol| symbol.name == "identity"));
+//! ```
+//!
+//! [`assert_exports`] compares them to a snapshot in `tests/exports`, so that
+//! changes to the exported ABI show up in review. Run the tests with
+//! `TEST_CDYLIB=overwrite` to write the snapshots.
+//!
+//! ```no_run
+//! let dylib_path = test_cdylib::build_file("tests/cdylibs/identity.rs");
+//! test_cdylib::assert_exports(&dylib_path, "identity");
+//! ```
 
 #![forbid(unsafe_code)]
+#![allow(clippy::test_attr_in_doctest)]
 
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 #[macro_use]
 mod path;
 
+mod builder;
+mod cache;
 mod cargo;
 mod dependencies;
+mod diagnostic;
 mod error;
+mod feature_graph;
 mod features;
+mod frontmatter;
+mod inherit;
 mod manifest;
+mod normalize;
+mod output;
+mod overlay;
 mod run;
 mod rustflags;
+mod snapshot;
+mod symbols;
+mod target;
+mod usage;
+
+pub use crate::builder::CdylibBuilder;
+pub use crate::diagnostic::{Diagnostic, DiagnosticSpan};
+pub use crate::error::{Error, Result};
+pub use crate::output::BuildOutput;
+pub use crate::symbols::{
+    exported_symbols, ExportedSymbol, SymbolBinding, SymbolKind, SymbolVisibility,
+};
 
 /// Builds the given file as a cdylib and returns the path to the compiled object.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_file`] for a fallible version.
 pub fn build_file<P: AsRef<Path>>(path: P) -> PathBuf {
-    run::run(path.as_ref()).unwrap()
+    try_build_file(path).unwrap()
+}
+
+/// Builds the given source code as a cdylib and returns the path to the
+/// compiled object.
+///
+/// The code is built like a file passed to [`build_file`]. See
+/// [`CdylibBuilder::source`] for how it is cached.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_source`] for a fallible version.
+pub fn build_source(name: &str, code: &str) -> PathBuf {
+    try_build_source(name, code).unwrap()
+}
+
+/// Builds the given items as a cdylib and returns the path to the compiled
+/// object.
+///
+/// This is a shorthand for [`build_source`] which names the library after a
+/// hash of its contents.
+///
+/// ```no_run
+/// let dylib_path = test_cdylib::cdylib! {
+///     #[no_mangle]
+///     pub extern "C" fn identity(x: i32) -> i32 {
+///         x
+///     }
+/// };
+/// ```
+#[macro_export]
+macro_rules! cdylib {
+    ($($code:tt)*) => {
+        $crate::build_source("inline", stringify!($($code)*))
+    };
 }
 
 /// Builds the current project as a cdylib and returns the path to the compiled object.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_current_project`] for a fallible
+/// version.
 pub fn build_current_project() -> PathBuf {
-    cargo::build_self_cdylib().unwrap()
+    try_build_current_project().unwrap()
 }
 
 /// Builds the given example as a cdylib and returns the path to the compiled object.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_example`] for a fallible version.
 pub fn build_example(name: &str) -> PathBuf {
-    cargo::build_example(name).unwrap()
+    try_build_example(name).unwrap()
+}
+
+/// Builds the library of the given workspace package as a cdylib and returns
+/// the path to the compiled object.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_package`] for a fallible version.
+pub fn build_package(name: &str) -> PathBuf {
+    try_build_package(name).unwrap()
+}
+
+/// Builds the library of the crate at the given path as a cdylib and returns
+/// the path to the compiled object.
+///
+/// # Panics
+///
+/// Panics if the build fails. See [`try_build_crate_at`] for a fallible version.
+pub fn build_crate_at<P: AsRef<Path>>(path: P) -> PathBuf {
+    try_build_crate_at(path).unwrap()
+}
+
+/// Builds the given file as a cdylib, which is expected to fail, and compares
+/// the compiler's errors and warnings to the `.stderr` file next to it.
+///
+/// Only the diagnostics of the file itself are compared, not e.g. warnings in
+/// the crate under test. Paths and hashes in the output are normalized, so
+/// that it doesn't depend on the machine. Set `TEST_CDYLIB=overwrite` to
+/// write the `.stderr` file instead.
+///
+/// # Panics
+///
+/// Panics if the build succeeds, fails before the file is compiled, e.g.
+/// because a dependency can't be resolved, or the output doesn't match. See
+/// [`try_build_file_expect_fail`] for a fallible version.
+pub fn build_file_expect_fail<P: AsRef<Path>>(path: P) {
+    if let Err(err) = try_build_file_expect_fail(path) {
+        panic!("{}", err);
+    }
+}
+
+/// Compares the symbols the given cdylib exports to the snapshot
+/// `tests/exports/<name>.txt`, so that a missing `#[no_mangle]` or a leaked
+/// internal symbol fails the test.
+///
+/// Set `TEST_CDYLIB=overwrite` to write the snapshot instead.
+///
+/// ```no_run
+/// let dylib_path = test_cdylib::build_current_project();
+/// test_cdylib::assert_exports(&dylib_path, "current_project");
+/// ```
+///
+/// # Panics
+///
+/// Panics if the symbols can't be read or don't match, showing which were
+/// added and removed. See [`try_assert_exports`] for a fallible version.
+pub fn assert_exports<P: AsRef<Path>>(artifact: P, name: &str) {
+    if let Err(err) = try_assert_exports(artifact, name) {
+        panic!("{}", err);
+    }
+}
+
+/// Builds the given file as a cdylib and returns the path to the compiled object.
+pub fn try_build_file<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    CdylibBuilder::file(path)
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds the given file as a cdylib, which is expected to fail, and compares
+/// the compiler's errors and warnings to the `.stderr` file next to it.
+pub fn try_build_file_expect_fail<P: AsRef<Path>>(path: P) -> Result<()> {
+    snapshot::expect_fail(path.as_ref())
+}
+
+/// Compares the symbols the given cdylib exports to the snapshot
+/// `tests/exports/<name>.txt`.
+pub fn try_assert_exports<P: AsRef<Path>>(artifact: P, name: &str) -> Result<()> {
+    snapshot::exports(artifact.as_ref(), name)
+}
+
+/// Builds the given source code as a cdylib and returns the path to the
+/// compiled object.
+pub fn try_build_source(name: &str, code: &str) -> Result<PathBuf> {
+    CdylibBuilder::source(name, code)
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds the current project as a cdylib and returns the path to the compiled object.
+pub fn try_build_current_project() -> Result<PathBuf> {
+    CdylibBuilder::current_project()
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds the given example as a cdylib and returns the path to the compiled object.
+pub fn try_build_example(name: &str) -> Result<PathBuf> {
+    CdylibBuilder::example(name)
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds the library of the given workspace package as a cdylib and returns
+/// the path to the compiled object.
+pub fn try_build_package(name: &str) -> Result<PathBuf> {
+    CdylibBuilder::package(name)
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds the library of the crate at the given path as a cdylib and returns
+/// the path to the compiled object.
+///
+/// The path is either the crate's directory or its `Cargo.toml`, relative to
+/// the current project.
+pub fn try_build_crate_at<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    CdylibBuilder::crate_at(path)
+        .build()
+        .map(|output| output.artifact)
+}
+
+/// Builds each of the given files as a cdylib using a single cargo invocation
+/// and returns the path to each compiled object.
+///
+/// A failure to build one file doesn't prevent the others from being built.
+///
+/// # Panics
+///
+/// Panics if cargo can't be run at all. See [`try_build_files`] for a
+/// fallible version.
+pub fn build_files<P: AsRef<Path>>(paths: &[P]) -> BTreeMap<PathBuf, Result<PathBuf>> {
+    try_build_files(paths).unwrap()
+}
+
+/// Builds each of the given examples as a cdylib using a single cargo
+/// invocation and returns the path to each compiled object.
+///
+/// A failure to build one example doesn't prevent the others from being
+/// built.
+///
+/// # Panics
+///
+/// Panics if cargo can't be run at all. See [`try_build_examples`] for a
+/// fallible version.
+pub fn build_examples(names: &[&str]) -> BTreeMap<String, Result<PathBuf>> {
+    try_build_examples(names).unwrap()
+}
+
+/// Builds each of the given files as a cdylib using a single cargo invocation
+/// and returns the path to each compiled object.
+pub fn try_build_files<P: AsRef<Path>>(paths: &[P]) -> Result<BTreeMap<PathBuf, Result<PathBuf>>> {
+    let paths: Vec<&Path> = paths.iter().map(AsRef::as_ref).collect();
+    let outputs = run::run_many(&paths, &CdylibBuilder::defaults())?;
+    Ok(paths
+        .into_iter()
+        .map(Path::to_owned)
+        .zip(outputs.into_iter().map(|output| output.map(|o| o.artifact)))
+        .collect())
+}
+
+/// Builds each of the given examples as a cdylib using a single cargo
+/// invocation and returns the path to each compiled object.
+pub fn try_build_examples(names: &[&str]) -> Result<BTreeMap<String, Result<PathBuf>>> {
+    let outputs = cargo::build_examples(names, &CdylibBuilder::defaults())?;
+    Ok(names
+        .iter()
+        .map(|&name| name.to_owned())
+        .zip(outputs.into_iter().map(|output| output.map(|o| o.artifact)))
+        .collect())
+}
+
+/// Passes the features the package is built with to its tests, so that
+/// fixtures are built with the same features. Call this from the package's
+/// build script, with `test-cdylib` as a build-dependency.
+///
+/// ```no_run
+/// // In the `main` function of build.rs:
+/// test_cdylib::export_features();
+/// ```
+///
+/// This sets `TEST_CDYLIB_FEATURES` for the tests, unless it's set while
+/// building them already.
+pub fn export_features() {
+    features::export();
 }
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap as Map;
 use std::ffi::OsStr;
 use std::path::PathBuf;
+use toml::Value;
 
 #[derive(Serialize, Debug)]
 pub struct Manifest {
@@ -10,13 +11,32 @@ pub struct Manifest {
     #[serde(skip_serializing_if = "Map::is_empty")]
     pub features: Map<String, Vec<String>>,
     pub dependencies: Map<String, Dependency>,
+    #[serde(rename = "build-dependencies", skip_serializing_if = "Map::is_empty")]
+    pub build_dependencies: Map<String, Dependency>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub target: Map<String, Target>,
     pub lib: Lib,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub workspace: Option<Workspace>,
+    pub lints: Option<Value>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WorkspaceManifest {
+    pub workspace: Workspace,
     #[serde(skip_serializing_if = "Map::is_empty")]
     pub patch: Map<String, RegistryPatch>,
     #[serde(skip_serializing_if = "Map::is_empty")]
     pub replace: Map<String, Patch>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub profile: Map<String, Value>,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct Target {
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub dependencies: Map<String, Dependency>,
+    #[serde(rename = "build-dependencies", skip_serializing_if = "Map::is_empty")]
+    pub build_dependencies: Map<String, Dependency>,
 }
 
 #[derive(Serialize, Debug)]
@@ -24,15 +44,35 @@ pub struct Package {
     pub name: String,
     pub version: String,
     pub edition: Edition,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<PathBuf>,
+    #[serde(rename = "rust-version", skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
     pub publish: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
 pub enum Edition {
     #[serde(rename = "2015")]
     E2015,
     #[serde(rename = "2018")]
+    #[default]
     E2018,
+    #[serde(rename = "2021")]
+    E2021,
+    #[serde(rename = "2024")]
+    E2024,
+}
+
+impl Edition {
+    /// The resolver cargo uses by default for a package of this edition.
+    pub fn default_resolver(self) -> &'static str {
+        match self {
+            Edition::E2015 | Edition::E2018 => "1",
+            Edition::E2021 => "2",
+            Edition::E2024 => "3",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -44,17 +84,22 @@ enum CrateType {
 #[derive(Serialize, Debug)]
 pub struct Lib {
     pub path: PathBuf,
+    #[serde(rename = "crate-type")]
     crate_type: [CrateType; 1],
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
 }
 impl Lib {
     pub fn new(path: PathBuf) -> Self {
         Self {
             path,
             crate_type: [CrateType::CDyLib],
+            rest: Map::new(),
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Clone, Debug)]
 pub struct Name(pub String);
 
@@ -68,13 +113,10 @@ pub struct Build {
     pub rustflags: Vec<&'static str>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct Workspace {}
-
-impl Default for Edition {
-    fn default() -> Self {
-        Edition::E2018
-    }
+#[derive(Serialize, Clone, Debug)]
+pub struct Workspace {
+    pub members: Vec<String>,
+    pub resolver: String,
 }
 
 impl AsRef<OsStr> for Name {
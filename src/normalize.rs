@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use crate::diagnostic::Diagnostic;
+
+/// Renders the errors and warnings of a failed build like rustc prints them,
+/// with anything specific to this machine or build replaced, so that the
+/// result can be compared to a snapshot.
+pub fn stderr(
+    diagnostics: &[Diagnostic],
+    source_dir: &Path,
+    workspace_dir: Option<&Path>,
+) -> String {
+    let mut stderr = String::new();
+    for diagnostic in diagnostics {
+        let is_summary = diagnostic.spans.is_empty() && diagnostic.message.starts_with("aborting");
+        if !(diagnostic.is_error() || diagnostic.is_warning()) || is_summary {
+            continue;
+        }
+        match &diagnostic.rendered {
+            Some(rendered) => stderr.push_str(rendered),
+            None => stderr.push_str(&format!("{}: {}\n", diagnostic.level, diagnostic.message)),
+        }
+        stderr.push('\n');
+    }
+
+    // The workspace is inside the target directory, which may be inside the
+    // source directory, so replace it first.
+    if let Some(workspace_dir) = workspace_dir {
+        stderr = replace_dir(&stderr, workspace_dir, "$WORKSPACE");
+    }
+    stderr = replace_dir(&stderr, source_dir, "$DIR");
+
+    let mut normalized = String::new();
+    for line in stderr.lines() {
+        let mut line = replace_hashes(line.trim_end());
+        if line.trim_start().starts_with("-->") {
+            line = line.replace('\\', "/");
+        }
+        normalized.push_str(&line);
+        normalized.push('\n');
+    }
+    let len = normalized.trim_end().len();
+    normalized.truncate(len);
+    normalized.push('\n');
+    normalized
+}
+
+fn replace_dir(text: &str, dir: &Path, replacement: &str) -> String {
+    let dir = dir.to_string_lossy();
+    let dir = dir.trim_end_matches(['/', '\\']);
+    if dir.is_empty() {
+        return text.to_owned();
+    }
+    text.replace(dir, replacement)
+}
+
+/// Replaces the hashes cargo and rustc add to file names and paths, like the
+/// `-0123456789abcdef` of a test binary or `/rustc/<commit>/` of the standard
+/// library's sources.
+fn replace_hashes(line: &str) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(index) = rest.find(['-', '/']) {
+        let (before, after) = rest.split_at(index);
+        result.push_str(before);
+        let separator = &after[..1];
+        let after = &after[1..];
+
+        let hex = after.bytes().take_while(u8::is_ascii_hexdigit).count();
+        let ends_word = !after[hex..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        if separator == "-" && hex == 16 && ends_word {
+            result.push_str("-HASH");
+            rest = &after[hex..];
+        } else if separator == "/" && hex == 40 && ends_word && result.ends_with("/rustc") {
+            result.truncate(result.len() - "/rustc".len());
+            result.push_str("$RUST");
+            rest = &after[hex..];
+        } else {
+            result.push_str(separator);
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[test]
+fn test_replace_hashes() {
+    assert_eq!(
+        replace_hashes("--> inline-0123456789abcdef.rs:1:2"),
+        "--> inline-HASH.rs:1:2"
+    );
+    assert_eq!(
+        replace_hashes("/rustc/0123456789abcdef0123456789abcdef01234567/library/core/src/lib.rs"),
+        "$RUST/library/core/src/lib.rs"
+    );
+    assert_eq!(replace_hashes("a -> b-c"), "a -> b-c");
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::diagnostic::Diagnostic;
+
+/// The result of a successful cdylib build.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct BuildOutput {
+    /// The path to the compiled cdylib, a copy of the one cargo built in a
+    /// directory of its own for this configuration.
+    pub artifact: PathBuf,
+    /// Every file cargo produced for the cdylib target, e.g. import
+    /// libraries.
+    pub filenames: Vec<PathBuf>,
+    /// The id of the package the cdylib belongs to.
+    pub package_id: String,
+    /// The name of the cargo target the cdylib was built from.
+    pub target_name: String,
+    /// The crate types the cargo target was built as.
+    pub crate_types: Vec<String>,
+    /// The cargo profile the cdylib was built with.
+    pub profile: String,
+    /// The target triple the cdylib was built for.
+    pub target: String,
+    /// Whether cargo reused an existing build instead of compiling.
+    pub fresh: bool,
+    /// How long the cargo invocation took, which is shared by all cdylibs
+    /// built with it.
+    pub duration: Duration,
+    /// The diagnostics the compiler emitted for the cdylib's package, e.g.
+    /// warnings.
+    pub diagnostics: Vec<Diagnostic>,
+}
@@ -0,0 +1,78 @@
+use toml::Value;
+
+use crate::error::{Error, Result};
+
+/// A manifest fragment to merge over a generated fixture manifest.
+#[derive(Clone, Debug)]
+pub(crate) enum Overlay {
+    Value(Value),
+    Toml(String),
+}
+
+impl Overlay {
+    /// Deep-merges the overlay into `manifest`. Tables are merged key by key;
+    /// any other value may only be set if the manifest doesn't already have a
+    /// different one.
+    pub fn apply(&self, manifest: &mut Value) -> Result<()> {
+        let overlay = match self {
+            Overlay::Value(value) => value.clone(),
+            Overlay::Toml(toml) => toml::from_str(toml)?,
+        };
+        merge(manifest, overlay, &mut String::new())
+    }
+}
+
+fn merge(base: &mut Value, overlay: Value, key: &mut String) -> Result<()> {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (name, value) in overlay {
+                let len = key.len();
+                if !key.is_empty() {
+                    key.push('.');
+                }
+                key.push_str(&name);
+                match base.get_mut(&name) {
+                    Some(existing) => merge(existing, value, key)?,
+                    None => {
+                        base.insert(name, value);
+                    }
+                }
+                key.truncate(len);
+            }
+            Ok(())
+        }
+        (base, overlay) if *base == overlay => Ok(()),
+        _ => Err(Error::ManifestConflict { key: key.clone() }),
+    }
+}
+
+#[test]
+fn test_merge() {
+    let mut manifest: Value = toml::from_str(
+        r#"
+        [package]
+        name = "fixture"
+
+        [lib]
+        crate-type = ["cdylib"]
+        "#,
+    )
+    .unwrap();
+
+    Overlay::Toml("lib.name = \"plugin\"\npackage.metadata.codegen = true".to_owned())
+        .apply(&mut manifest)
+        .unwrap();
+    assert_eq!(manifest["lib"]["name"].as_str(), Some("plugin"));
+    assert_eq!(
+        manifest["package"]["metadata"]["codegen"].as_bool(),
+        Some(true)
+    );
+
+    Overlay::Toml("package.name = \"fixture\"".to_owned())
+        .apply(&mut manifest)
+        .unwrap();
+    let err = Overlay::Toml("package.name = \"other\"".to_owned())
+        .apply(&mut manifest)
+        .unwrap_err();
+    assert!(matches!(err, Error::ManifestConflict { key } if key == "package.name"));
+}
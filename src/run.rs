@@ -1,35 +1,101 @@
-use std::collections::BTreeMap as Map;
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
 use std::env;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
+use toml::Value;
 
+use crate::builder::CdylibBuilder;
+use crate::cache;
 use crate::cargo;
 use crate::dependencies::{self, Dependency};
 use crate::error::{Error, Result};
-use crate::features;
-use crate::manifest::{Build, Config, Lib, Manifest, Package, Workspace};
+use crate::feature_graph;
+use crate::frontmatter;
+use crate::manifest::{Build, Config, Lib, Manifest, Package, Workspace, WorkspaceManifest};
+use crate::output::BuildOutput;
 use crate::rustflags;
+use crate::usage;
 
 #[derive(Debug)]
 pub struct Project {
+    /// The generated scratch workspace shared by all fixtures of a crate.
+    pub workspace_dir: PathBuf,
+    /// The generated package of this fixture, a member of `workspace_dir`.
     pub dir: PathBuf,
     source_dir: PathBuf,
-    pub target_dir: PathBuf,
     pub name: String,
     pub features: Option<Vec<String>>,
-    workspace: PathBuf,
+    build_script: Option<PathBuf>,
+    /// The root manifest of `workspace_dir`, without any members yet.
+    root_manifest: WorkspaceManifest,
 }
 
-pub(crate) fn run(path: &Path) -> Result<PathBuf> {
-    check_exists(path)?;
-    let project = prepare(path)?;
-    cargo::build_cdylib(&project)
+pub(crate) fn run(path: &Path, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let metadata = cargo::metadata()?;
+    let _lock = cache::lock_dir(&workspace_dir(builder, &metadata)?)?;
+    let project = prepare(path, builder, &metadata)?;
+    write_workspace(&[&project])?;
+    cargo::build_cdylib(&project, builder)
+}
+
+/// Builds the given source code like a file.
+pub(crate) fn run_source(name: &str, code: &str, builder: &CdylibBuilder) -> Result<BuildOutput> {
+    let metadata = cargo::metadata()?;
+    let workspace_dir = workspace_dir(builder, &metadata)?;
+    let _lock = cache::lock_dir(&workspace_dir)?;
+
+    // Name the file after its contents so identical sources share one build.
+    let file_name = format!("{}-{:016x}.rs", name, fnv1a(code.as_bytes()));
+    let path = path!(workspace_dir / "sources" / file_name);
+    fs::create_dir_all(path!(workspace_dir / "sources"))?;
+    write_if_changed(&path, code)?;
+
+    let project = prepare(&path, builder, &metadata)?;
+    write_workspace(&[&project])?;
+    cargo::build_cdylib(&project, builder)
 }
 
-fn prepare(path: &Path) -> Result<Project> {
+/// Builds several files with a single cargo invocation.
+pub(crate) fn run_many(
+    paths: &[&Path],
+    builder: &CdylibBuilder,
+) -> Result<Vec<Result<BuildOutput>>> {
     let metadata = cargo::metadata()?;
-    let target_dir = metadata.target_directory;
-    let workspace = metadata.workspace_root;
+    let _lock = cache::lock_dir(&workspace_dir(builder, &metadata)?)?;
+    let projects: Vec<Result<Project>> = paths
+        .iter()
+        .map(|path| prepare(path, builder, &metadata))
+        .collect();
+
+    let prepared: Vec<&Project> = projects.iter().filter_map(|p| p.as_ref().ok()).collect();
+    let mut outputs = if prepared.is_empty() {
+        Vec::new().into_iter()
+    } else {
+        write_workspace(&prepared)?;
+        cargo::build_cdylibs(&prepared, builder)?.into_iter()
+    };
+
+    // Outputs are in the same order as the successfully prepared projects.
+    Ok(projects
+        .into_iter()
+        .map(|project| project.and_then(|_| outputs.next().unwrap()))
+        .collect())
+}
+
+fn workspace_dir(builder: &CdylibBuilder, metadata: &cargo::Metadata) -> Result<PathBuf> {
+    let crate_name = env::var("CARGO_PKG_NAME").map_err(Error::PkgName)?;
+    // Keep a separate workspace per profile so that building one profile
+    // never overwrites the artifacts of another.
+    Ok(path!(
+        metadata.target_directory / "cdylibs" / crate_name / builder.profile
+    ))
+}
+
+fn prepare(path: &Path, builder: &CdylibBuilder, metadata: &cargo::Metadata) -> Result<Project> {
+    check_exists(path)?;
+    if let Some(build_script) = &builder.build_script {
+        check_exists(build_script)?;
+    }
 
     let crate_name = env::var("CARGO_PKG_NAME").map_err(Error::PkgName)?;
     let test_name = path.file_stem().unwrap();
@@ -38,67 +104,161 @@ fn prepare(path: &Path) -> Result<Project> {
         .map(PathBuf::from)
         .ok_or(Error::ProjectDir)?;
 
-    let features = features::find();
+    let build_script = builder
+        .build_script
+        .as_ref()
+        .map(|build_script| source_dir.join(build_script));
+
+    let workspace_dir = workspace_dir(builder, metadata)?;
+
+    let workspace_manifest = dependencies::get_workspace_manifest(&metadata.workspace_root)?;
+    let source_manifest = dependencies::get_manifest(&source_dir, &workspace_manifest)?;
+    let root_manifest = make_workspace_manifest(&source_manifest, workspace_manifest);
+
+    let features = builder.detected_features();
     let mut project = Project {
-        dir: path!(target_dir / "cdylibs" / crate_name / test_name),
+        dir: path!(workspace_dir / "fixtures" / test_name),
+        workspace_dir,
         source_dir,
-        target_dir,
         name: format!("{}-cdylib-{}", crate_name, test_name.to_string_lossy()),
         features,
-        workspace,
+        build_script,
+        root_manifest,
     };
 
-    let manifest = make_manifest(&crate_name, &project, path)?;
+    let manifest = make_manifest(&crate_name, &project, path, builder, source_manifest)?;
+    let mut manifest = Value::try_from(&manifest)?;
+    for overlay in &builder.overlays {
+        overlay.apply(&mut manifest)?;
+    }
     let manifest_toml = toml::to_string(&manifest)?;
 
     let config = make_config();
     let config_toml = toml::to_string(&config)?;
 
     if let Some(enabled_features) = &mut project.features {
-        enabled_features.retain(|feature| manifest.features.contains_key(feature));
+        let features = manifest.get("features").and_then(Value::as_table);
+        enabled_features.retain(|feature| features.is_some_and(|f| f.contains_key(feature)));
     }
 
-    fs::create_dir_all(path!(project.dir / ".cargo"))?;
-    fs::write(path!(project.dir / ".cargo" / "config"), config_toml)?;
-    fs::write(path!(project.dir / "Cargo.toml"), manifest_toml)?;
+    fs::create_dir_all(path!(project.workspace_dir / ".cargo"))?;
+    fs::create_dir_all(&project.dir)?;
+    write_if_changed(
+        &path!(project.workspace_dir / ".cargo" / "config.toml"),
+        &config_toml,
+    )?;
+    write_if_changed(&path!(project.dir / "Cargo.toml"), &manifest_toml)?;
 
     Ok(project)
 }
 
-fn make_manifest(crate_name: &str, project: &Project, cdylib_path: &Path) -> Result<Manifest> {
-    let source_manifest = dependencies::get_manifest(&project.source_dir);
-    let workspace_manifest = dependencies::get_workspace_manifest(&project.workspace);
-
-    let features = source_manifest
-        .features
-        .keys()
-        .map(|feature| {
-            let enable = format!("{}/{}", crate_name, feature);
-            (feature.clone(), vec![enable])
+/// Writes the root manifest of the scratch workspace with only the given
+/// projects as members. Cargo resolves every member of a workspace, so a
+/// fixture with a broken manifest, or one whose source is gone, would
+/// otherwise fail the builds of all the others.
+fn write_workspace(projects: &[&Project]) -> Result<()> {
+    let mut root_manifest = projects[0].root_manifest.clone();
+    root_manifest.workspace.members = projects
+        .iter()
+        .map(|project| {
+            let name = project.dir.file_name().unwrap().to_string_lossy();
+            format!("fixtures/{}", name)
         })
         .collect();
+    write_if_changed(
+        &path!(projects[0].workspace_dir / "Cargo.toml"),
+        &toml::to_string(&root_manifest)?,
+    )
+}
+
+fn make_workspace_manifest(
+    source_manifest: &dependencies::Manifest,
+    workspace_manifest: dependencies::WorkspaceManifest,
+) -> WorkspaceManifest {
+    let resolver = source_manifest
+        .package
+        .resolver
+        .clone()
+        .or(workspace_manifest.workspace.resolver)
+        .unwrap_or_else(|| {
+            source_manifest
+                .package
+                .edition
+                .default_resolver()
+                .to_owned()
+        });
+
+    WorkspaceManifest {
+        // The fixtures being built are members so that they share one target
+        // directory and only compile their dependencies once.
+        workspace: Workspace {
+            members: Vec::new(),
+            resolver,
+        },
+        // Within a workspace, only the [patch] and [replace] sections in
+        // the workspace root's Cargo.toml are applied by Cargo.
+        patch: workspace_manifest.patch,
+        replace: workspace_manifest.replace,
+        // Profiles are likewise only read from the workspace root, and are
+        // needed to build with a custom profile.
+        profile: workspace_manifest.profile,
+    }
+}
+
+fn make_manifest(
+    crate_name: &str,
+    project: &Project,
+    cdylib_path: &Path,
+    builder: &CdylibBuilder,
+    source_manifest: dependencies::Manifest,
+) -> Result<Manifest> {
+    let cdylib_path = project.source_dir.join(cdylib_path);
+    let features = feature_graph::all_features(&source_manifest);
+    let mut source_manifest = source_manifest;
+    if !builder.all_dependencies {
+        prune_dependencies(&mut source_manifest, &cdylib_path, project, builder)?;
+    }
 
     let mut manifest = Manifest {
         package: Package {
             name: project.name.clone(),
             version: "0.0.0".to_owned(),
             edition: source_manifest.package.edition,
+            build: project.build_script.clone(),
+            rust_version: source_manifest.package.rust_version,
             publish: false,
         },
-        lib: Lib::new(project.source_dir.join(cdylib_path)),
-        features,
+        lib: Lib::new(cdylib_path.clone()),
+        features: Map::new(),
         dependencies: Map::new(),
-        workspace: Some(Workspace {}),
-        // Within a workspace, only the [patch] and [replace] sections in
-        // the workspace root's Cargo.toml are applied by Cargo.
-        patch: workspace_manifest.patch,
-        replace: workspace_manifest.replace,
+        build_dependencies: Map::new(),
+        target: Map::new(),
+        lints: source_manifest.lints,
     };
 
     manifest.dependencies.extend(source_manifest.dependencies);
     manifest
         .dependencies
-        .extend(source_manifest.dev_dependencies);
+        .extend(required(source_manifest.dev_dependencies));
+    for (cfg, target) in source_manifest.target {
+        let fixture_target = manifest.target.entry(cfg).or_default();
+        fixture_target.dependencies.extend(target.dependencies);
+        fixture_target
+            .dependencies
+            .extend(required(target.dev_dependencies));
+        if project.build_script.is_some() {
+            fixture_target
+                .build_dependencies
+                .extend(target.build_dependencies);
+        }
+    }
+    // Build-dependencies are only compiled for a build script, so skip them
+    // when the fixture doesn't have one.
+    if project.build_script.is_some() {
+        manifest
+            .build_dependencies
+            .extend(source_manifest.build_dependencies);
+    }
     manifest.dependencies.insert(
         crate_name.to_owned(),
         Dependency {
@@ -106,13 +266,68 @@ fn make_manifest(crate_name: &str, project: &Project, cdylib_path: &Path) -> Res
             path: Some(project.source_dir.clone()),
             default_features: false,
             features: Vec::new(),
+            optional: false,
             rest: Map::new(),
         },
     );
 
+    frontmatter::read(&cdylib_path)?.apply(&mut manifest);
+
+    // Translated against the final dependencies, so that features never
+    // refer to dependencies the fixture doesn't have.
+    for (feature, values) in feature_graph::translate(crate_name, &features, &manifest) {
+        manifest.features.entry(feature).or_insert(values);
+    }
+
     Ok(manifest)
 }
 
+/// Only normal dependencies of the crate can be optional, so clear it on its
+/// dev-dependencies rather than turning them into features of the fixture.
+fn required(mut dependencies: Map<String, Dependency>) -> Map<String, Dependency> {
+    for dependency in dependencies.values_mut() {
+        dependency.optional = false;
+    }
+    dependencies
+}
+
+fn prune_dependencies(
+    manifest: &mut dependencies::Manifest,
+    cdylib_path: &Path,
+    project: &Project,
+    builder: &CdylibBuilder,
+) -> Result<()> {
+    let read =
+        |path: &Path| fs::read_to_string(path).map_err(|err| Error::Open(path.to_owned(), err));
+    let mut used = usage::used_crates(&read(cdylib_path)?);
+    used.extend(
+        builder
+            .dependencies
+            .iter()
+            .map(|name| name.replace('-', "_")),
+    );
+    retain_used(&used, &mut manifest.dependencies);
+    retain_used(&used, &mut manifest.dev_dependencies);
+    for target in manifest.target.values_mut() {
+        retain_used(&used, &mut target.dependencies);
+        retain_used(&used, &mut target.dev_dependencies);
+    }
+
+    if let Some(build_script) = &project.build_script {
+        let used = usage::used_crates(&read(build_script)?);
+        retain_used(&used, &mut manifest.build_dependencies);
+        for target in manifest.target.values_mut() {
+            retain_used(&used, &mut target.build_dependencies);
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the dependencies whose crate name is in `used`.
+fn retain_used(used: &Set<String>, dependencies: &mut Map<String, Dependency>) {
+    dependencies.retain(|name, _| used.contains(&name.replace('-', "_")));
+}
+
 fn make_config() -> Config {
     Config {
         build: Build {
@@ -121,7 +336,25 @@ fn make_config() -> Config {
     }
 }
 
-fn check_exists(path: &Path) -> Result<()> {
+fn write_if_changed(path: &Path, contents: &str) -> Result<()> {
+    // Rewriting an unchanged file would needlessly disturb concurrent builds
+    // of other fixtures in the same workspace.
+    if fs::read_to_string(path).ok().as_deref() == Some(contents) {
+        return Ok(());
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// A stable hash, unlike `DefaultHasher` whose output may change between
+/// Rust releases.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn check_exists(path: &Path) -> Result<()> {
     if path.exists() {
         return Ok(());
     }
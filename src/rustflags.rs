@@ -15,16 +15,44 @@ pub fn make_vec() -> Vec<&'static str> {
     rustflags
 }
 
-pub fn set_env(cmd: &mut Command) {
+pub fn set_env(cmd: &mut Command, extra: &[String]) {
+    // Only override RUSTFLAGS when it's already set, as cargo ignores the
+    // rustflags of its config files when it is. Changing it also forces the
+    // dependencies to be rebuilt.
     let mut rustflags = match env::var_os(RUSTFLAGS) {
         Some(rustflags) => rustflags,
+        None if !extra.is_empty() => {
+            cmd.arg("--config").arg(config_arg(extra));
+            return;
+        }
         None => return,
     };
 
-    for flag in make_vec() {
-        rustflags.push(" ");
+    for flag in make_vec()
+        .into_iter()
+        .chain(extra.iter().map(String::as_str))
+    {
+        if !rustflags.is_empty() {
+            rustflags.push(" ");
+        }
         rustflags.push(flag);
     }
 
     cmd.env(RUSTFLAGS, rustflags);
 }
+
+/// Cargo appends arrays given with `--config` to those of its config files,
+/// so these flags add to the `build.rustflags` the project configures.
+fn config_arg(extra: &[String]) -> String {
+    let flags = extra.iter().cloned().map(toml::Value::String).collect();
+    format!("build.rustflags={}", toml::Value::Array(flags))
+}
+
+#[test]
+fn test_config_arg() {
+    let extra = vec!["-Ctarget-cpu=native".to_owned(), "--cfg=a=\"b\"".to_owned()];
+    assert_eq!(
+        config_arg(&extra),
+        r#"build.rustflags=["-Ctarget-cpu=native", '--cfg=a="b"']"#,
+    );
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::builder::CdylibBuilder;
+use crate::diagnostic::Diagnostic;
+use crate::error::{Error, Result};
+use crate::normalize;
+use crate::symbols::{self, SymbolKind};
+
+/// Builds the given file, which is expected to fail, and compares its
+/// normalized diagnostics to the `.stderr` snapshot next to it.
+pub fn expect_fail(path: &Path) -> Result<()> {
+    let source_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok_or(Error::ProjectDir)?;
+
+    let mut builder = CdylibBuilder::file(path);
+    builder.silent = true;
+    let err = match builder.build() {
+        Ok(output) => {
+            return Err(Error::UnexpectedSuccess {
+                artifact: output.artifact,
+            })
+        }
+        Err(err) => err,
+    };
+    let (diagnostics, project_dir) = match &err {
+        Error::CompileError {
+            diagnostics,
+            project_dir,
+            ..
+        } if diagnostics.iter().any(Diagnostic::is_error) => (diagnostics, project_dir),
+        // Cargo failed before compiling the fixture, e.g. to resolve its
+        // dependencies, so there's no output to compare.
+        _ => return Err(err),
+    };
+
+    let workspace_dir = project_dir
+        .as_deref()
+        .and_then(|dir| dir.parent()?.parent());
+    let actual = normalize::stderr(diagnostics, &source_dir, workspace_dir);
+    check(&source_dir.join(path).with_extension("stderr"), &actual)
+}
+
+/// Compares the symbols the given cdylib exports to the snapshot
+/// `tests/exports/<name>.txt`, which lists one symbol per line.
+pub fn exports(artifact: &Path, name: &str) -> Result<()> {
+    let source_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok_or(Error::ProjectDir)?;
+
+    let mut actual = String::new();
+    for symbol in symbols::exported_symbols(artifact)? {
+        let kind = match symbol.kind {
+            SymbolKind::Function => "function",
+            SymbolKind::Data => "data",
+            _ => "other",
+        };
+        actual.push_str(&format!("{} {}\n", kind, symbol.name));
+    }
+    let path = source_dir
+        .join("tests")
+        .join("exports")
+        .join(format!("{}.txt", name));
+    check(&path, &actual)
+}
+
+/// Compares `actual` to the snapshot at `path`, or writes it there if
+/// `TEST_CDYLIB=overwrite` is set.
+pub fn check(path: &Path, actual: &str) -> Result<()> {
+    if env::var_os("TEST_CDYLIB").is_some_and(|mode| mode == "overwrite") {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, actual)?;
+        return Ok(());
+    }
+
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => Some(expected.replace("\r\n", "\n")),
+        Err(_) if !path.exists() => None,
+        Err(err) => return Err(Error::Open(path.to_owned(), err)),
+    };
+    if expected.as_deref() == Some(actual) {
+        return Ok(());
+    }
+    Err(Error::SnapshotMismatch {
+        path: path.to_owned(),
+        expected,
+        actual: actual.to_owned(),
+    })
+}
+
+/// A line based diff of `expected` and `actual`, with removed lines prefixed
+/// with `-`, added ones with `+` and unchanged ones with a space.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // The length of the longest common subsequence of the suffixes.
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push_str(&format!(" {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            diff.push_str(&format!("-{}\n", expected[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[test]
+fn test_diff() {
+    assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), " a\n-b\n c\n+d\n");
+    assert_eq!(diff("", "a\n"), "+a\n");
+    assert_eq!(diff("a\n", "b\n"), "-a\n+b\n");
+}
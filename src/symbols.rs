@@ -0,0 +1,300 @@
+use object::read::elf::{ElfFile, FileHeader, Sym};
+use object::{elf, Object, ObjectSection, ObjectSymbol, ReadRef, SectionKind, SymbolScope};
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// A symbol exported by a cdylib.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExportedSymbol {
+    /// The name other code links to, without the leading underscore Mach-O
+    /// adds.
+    pub name: String,
+    /// The name as it appears in Rust, if it's a mangled Rust name.
+    pub demangled: Option<String>,
+    /// Whether the symbol is a function or data.
+    pub kind: SymbolKind,
+    /// The size in bytes, or 0 if the format doesn't record it.
+    pub size: u64,
+    /// Whether another definition may take precedence.
+    pub binding: SymbolBinding,
+    /// How the symbol may be referred to from other objects.
+    pub visibility: SymbolVisibility,
+}
+
+/// What an [`ExportedSymbol`] refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum SymbolKind {
+    /// A function.
+    Function,
+    /// A static or thread local variable.
+    Data,
+    /// Anything else.
+    Unknown,
+}
+
+/// The binding of an [`ExportedSymbol`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum SymbolBinding {
+    /// A normal definition.
+    Global,
+    /// A definition that another one of the same name takes precedence over.
+    Weak,
+}
+
+/// The visibility of an [`ExportedSymbol`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum SymbolVisibility {
+    /// Visible to other objects, which may also interpose their own
+    /// definition.
+    Default,
+    /// Visible to other objects, but references from within the cdylib
+    /// always use its own definition. Only ELF distinguishes this.
+    Protected,
+}
+
+/// Returns the symbols the given cdylib exports, sorted by name, without
+/// loading it.
+///
+/// ELF files are read from their dynamic symbol table, Mach-O files from
+/// their symbol table and PE files from their export table. PE files don't
+/// record sizes, and their exports are only known to be functions if they
+/// point into executable code.
+///
+/// ```no_run
+/// let dylib_path = test_cdylib::build_current_project();
+/// let functions: Vec<String> = test_cdylib::exported_symbols(&dylib_path)
+///     .unwrap()
+///     .into_iter()
+///     .filter(|symbol| symbol.kind == test_cdylib::SymbolKind::Function)
+///     .map(|symbol| symbol.name)
+///     .collect();
+/// assert_eq!(functions, ["identity"]);
+/// ```
+pub fn exported_symbols<P: AsRef<Path>>(path: P) -> Result<Vec<ExportedSymbol>> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|err| Error::Open(path.to_owned(), err))?;
+    parse(&data).map_err(|source| Error::Object {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn parse(data: &[u8]) -> object::Result<Vec<ExportedSymbol>> {
+    let file = object::File::parse(data)?;
+    let mut symbols = match &file {
+        object::File::Elf32(elf) => elf_symbols(elf),
+        object::File::Elf64(elf) => elf_symbols(elf),
+        object::File::Pe32(_) | object::File::Pe64(_) => pe_symbols(&file)?,
+        _ => macho_symbols(&file),
+    };
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    symbols.dedup();
+    Ok(symbols)
+}
+
+fn elf_symbols<'data, Elf, R>(file: &ElfFile<'data, Elf, R>) -> Vec<ExportedSymbol>
+where
+    Elf: FileHeader,
+    R: ReadRef<'data>,
+{
+    file.dynamic_symbols()
+        // Only defined symbols with default or protected visibility.
+        .filter(|symbol| symbol.scope() == SymbolScope::Dynamic)
+        .filter_map(|symbol| {
+            let visibility = match symbol.elf_symbol().st_visibility() {
+                elf::STV_PROTECTED => SymbolVisibility::Protected,
+                _ => SymbolVisibility::Default,
+            };
+            Some(ExportedSymbol::new(
+                symbol.name().ok()?,
+                symbol.kind(),
+                symbol.size(),
+                symbol.is_weak(),
+                visibility,
+            ))
+        })
+        .collect()
+}
+
+fn macho_symbols(file: &object::File) -> Vec<ExportedSymbol> {
+    file.symbols()
+        .filter(|symbol| symbol.scope() == SymbolScope::Dynamic && !symbol.is_undefined())
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            Some(ExportedSymbol::new(
+                name.strip_prefix('_').unwrap_or(name),
+                symbol.kind(),
+                symbol.size(),
+                symbol.is_weak(),
+                SymbolVisibility::Default,
+            ))
+        })
+        .collect()
+}
+
+fn pe_symbols(file: &object::File) -> object::Result<Vec<ExportedSymbol>> {
+    let exports = file.exports()?;
+    Ok(exports
+        .iter()
+        .map(|export| {
+            // Both this and the section addresses include the image base.
+            let address = export.address();
+            let section = file.sections().find(|section| {
+                (section.address()..section.address() + section.size()).contains(&address)
+            });
+            let kind = match section.map(|section| section.kind()) {
+                Some(SectionKind::Text) => object::SymbolKind::Text,
+                _ => object::SymbolKind::Data,
+            };
+            let name = String::from_utf8_lossy(export.name());
+            ExportedSymbol::new(&name, kind, 0, false, SymbolVisibility::Default)
+        })
+        .collect())
+}
+
+impl ExportedSymbol {
+    fn new(
+        name: &str,
+        kind: object::SymbolKind,
+        size: u64,
+        is_weak: bool,
+        visibility: SymbolVisibility,
+    ) -> Self {
+        ExportedSymbol {
+            name: name.to_owned(),
+            demangled: rustc_demangle::try_demangle(name)
+                .ok()
+                .map(|name| format!("{:#}", name)),
+            kind: match kind {
+                object::SymbolKind::Text => SymbolKind::Function,
+                object::SymbolKind::Data | object::SymbolKind::Tls => SymbolKind::Data,
+                _ => SymbolKind::Unknown,
+            },
+            size,
+            binding: if is_weak {
+                SymbolBinding::Weak
+            } else {
+                SymbolBinding::Global
+            },
+            visibility,
+        }
+    }
+}
+
+#[test]
+fn test_demangle() {
+    let symbol = ExportedSymbol::new(
+        "_ZN4core3fmt5write17h0123456789abcdefE",
+        object::SymbolKind::Text,
+        8,
+        false,
+        SymbolVisibility::Default,
+    );
+    assert_eq!(symbol.demangled.as_deref(), Some("core::fmt::write"));
+    assert_eq!(symbol.kind, SymbolKind::Function);
+
+    let symbol = ExportedSymbol::new(
+        "identity",
+        object::SymbolKind::Text,
+        8,
+        true,
+        SymbolVisibility::Default,
+    );
+    assert_eq!(symbol.demangled, None);
+    assert_eq!(symbol.binding, SymbolBinding::Weak);
+}
+
+/// A minimal 64-bit PE DLL exporting the function `identity` from `.text` and
+/// the data `VALUE` from `.edata`.
+#[cfg(test)]
+fn pe_dll() -> Vec<u8> {
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    fn section(name: &[u8], rva: u32, file_offset: u32, characteristics: u32) -> Vec<u8> {
+        let mut header = vec![0; 40];
+        put(&mut header, 0, name);
+        put(&mut header, 8, &0x200u32.to_le_bytes());
+        put(&mut header, 12, &rva.to_le_bytes());
+        put(&mut header, 16, &0x200u32.to_le_bytes());
+        put(&mut header, 20, &file_offset.to_le_bytes());
+        put(&mut header, 36, &characteristics.to_le_bytes());
+        header
+    }
+
+    let mut data = vec![0; 0x600];
+    put(&mut data, 0, b"MZ");
+    put(&mut data, 0x3c, &0x40u32.to_le_bytes());
+    put(&mut data, 0x40, b"PE\0\0");
+
+    // COFF header: x86-64, two sections, a DLL.
+    put(&mut data, 0x44, &0x8664u16.to_le_bytes());
+    put(&mut data, 0x46, &2u16.to_le_bytes());
+    put(&mut data, 0x54, &240u16.to_le_bytes());
+    put(&mut data, 0x56, &0x2022u16.to_le_bytes());
+
+    // PE32+ optional header with the export table at 0x2000.
+    let optional = 0x58;
+    put(&mut data, optional, &0x20bu16.to_le_bytes());
+    put(&mut data, optional + 24, &0x1_8000_0000u64.to_le_bytes());
+    put(&mut data, optional + 32, &0x1000u32.to_le_bytes());
+    put(&mut data, optional + 36, &0x200u32.to_le_bytes());
+    put(&mut data, optional + 56, &0x3000u32.to_le_bytes());
+    put(&mut data, optional + 60, &0x200u32.to_le_bytes());
+    put(&mut data, optional + 108, &16u32.to_le_bytes());
+    put(&mut data, optional + 112, &0x2000u32.to_le_bytes());
+    put(&mut data, optional + 116, &0x80u32.to_le_bytes());
+
+    let sections = optional + 240;
+    put(
+        &mut data,
+        sections,
+        &section(b".text", 0x1000, 0x200, 0x6000_0020),
+    );
+    put(
+        &mut data,
+        sections + 40,
+        &section(b".edata", 0x2000, 0x400, 0x4000_0040),
+    );
+    put(&mut data, 0x200, &[0x89, 0xf8, 0xc3]);
+
+    // The export directory, followed by its tables and strings. Names are
+    // sorted, so `VALUE` comes first.
+    let edata = 0x400;
+    let directory: [u32; 10] = [0, 0, 0, 0x2040, 1, 2, 2, 0x2028, 0x2030, 0x2038];
+    for (i, value) in directory.iter().enumerate() {
+        put(&mut data, edata + 4 * i, &value.to_le_bytes());
+    }
+    put(&mut data, edata + 0x28, &0x1000u32.to_le_bytes());
+    put(&mut data, edata + 0x2c, &0x2100u32.to_le_bytes());
+    put(&mut data, edata + 0x30, &0x2050u32.to_le_bytes());
+    put(&mut data, edata + 0x34, &0x2060u32.to_le_bytes());
+    put(&mut data, edata + 0x38, &1u16.to_le_bytes());
+    put(&mut data, edata + 0x3a, &0u16.to_le_bytes());
+    put(&mut data, edata + 0x40, b"test.dll\0");
+    put(&mut data, edata + 0x50, b"VALUE\0");
+    put(&mut data, edata + 0x60, b"identity\0");
+    data
+}
+
+#[test]
+fn test_pe_symbols() {
+    let symbols = parse(&pe_dll()).unwrap();
+    let names: Vec<(&str, SymbolKind)> = symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.kind))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("VALUE", SymbolKind::Data),
+            ("identity", SymbolKind::Function)
+        ]
+    );
+}
@@ -0,0 +1,86 @@
+use std::env;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::error::{Error, Result};
+
+/// Returns the host triple rustc builds for when no `--target` is given.
+pub fn host() -> Result<String> {
+    static HOST: OnceLock<String> = OnceLock::new();
+    if let Some(host) = HOST.get() {
+        return Ok(host.clone());
+    }
+
+    let mut cmd = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()));
+    cmd.arg("-vV");
+    let command = format!("{} -vV", cmd.get_program().to_string_lossy());
+    let output = cmd.output().map_err(|source| Error::RustcMissing {
+        command: command.clone(),
+        source,
+    })?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    let host = version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .ok_or_else(|| Error::HostTriple {
+            command,
+            output: version.clone().into_owned(),
+        })?;
+    Ok(HOST.get_or_init(|| host.trim().to_owned()).clone())
+}
+
+/// The extension of a cdylib built for the given target.
+pub fn cdylib_extension(triple: &str) -> &'static str {
+    if triple.contains("-windows") {
+        "dll"
+    } else if triple.contains("-apple-") {
+        "dylib"
+    } else if triple.starts_with("wasm") {
+        "wasm"
+    } else {
+        "so"
+    }
+}
+
+/// Whether rustc builds cdylibs for the given target. On the targets listed
+/// here it drops the `cdylib` crate type, so a static library is used instead.
+pub fn supports_cdylib(triple: &str) -> bool {
+    // These musl targets link the C runtime statically by default.
+    let is_static_musl = triple.contains("-unknown-linux-musl");
+    let is_bare_metal = triple
+        .split('-')
+        .any(|part| part == "none" || part == "uefi");
+    !(is_static_musl || is_bare_metal)
+}
+
+/// The extension of a static library built for the given target, which is
+/// what's left of a cdylib on targets that don't support them, e.g. musl.
+pub fn staticlib_extension(triple: &str) -> &'static str {
+    if triple.ends_with("-windows-msvc") {
+        "lib"
+    } else {
+        "a"
+    }
+}
+
+#[test]
+fn test_extensions() {
+    assert_eq!(cdylib_extension("x86_64-unknown-linux-gnu"), "so");
+    assert_eq!(cdylib_extension("x86_64-unknown-linux-musl"), "so");
+    assert_eq!(cdylib_extension("aarch64-apple-darwin"), "dylib");
+    assert_eq!(cdylib_extension("x86_64-pc-windows-gnu"), "dll");
+    assert_eq!(cdylib_extension("wasm32-unknown-unknown"), "wasm");
+    assert_eq!(staticlib_extension("x86_64-pc-windows-msvc"), "lib");
+    assert_eq!(staticlib_extension("x86_64-unknown-linux-musl"), "a");
+}
+
+#[test]
+fn test_supports_cdylib() {
+    assert!(supports_cdylib("x86_64-unknown-linux-gnu"));
+    assert!(supports_cdylib("x86_64-pc-windows-msvc"));
+    assert!(supports_cdylib("wasm32-unknown-unknown"));
+    assert!(!supports_cdylib("x86_64-unknown-linux-musl"));
+    assert!(!supports_cdylib("armv7-unknown-linux-musleabihf"));
+    assert!(!supports_cdylib("thumbv7em-none-eabihf"));
+    assert!(!supports_cdylib("x86_64-unknown-uefi"));
+}
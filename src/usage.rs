@@ -0,0 +1,232 @@
+use std::collections::BTreeSet as Set;
+
+/// Finds the names of the crates a piece of Rust source refers to, i.e. every
+/// path root like `serde` in `serde::Serialize`, every `extern crate` and the
+/// roots of every `use` item, like `libc` in `use {libc as c};`.
+///
+/// This is a lightweight scan rather than a real parse, so it may find more
+/// names than are actually crates, but skips comments and string literals.
+pub fn used_crates(source: &str) -> Set<String> {
+    let tokens = tokenize(source);
+    let token = |i: Option<usize>| i.and_then(|i| tokens.get(i)).map(String::as_str);
+
+    let mut crates = Set::new();
+    for (i, ident) in tokens.iter().enumerate() {
+        if ident == "use" {
+            crates.extend(use_roots(&tokens[i + 1..]));
+        }
+        if !is_ident(ident) || KEYWORDS.contains(&ident.as_str()) {
+            continue;
+        }
+        let prev = token(i.checked_sub(1));
+        let prev2 = token(i.checked_sub(2));
+        // `::log`, unless it's the rest of a path like `std::log`.
+        let is_global_path =
+            prev == Some("::") && prev2.is_none_or(|t| !is_ident(t) || KEYWORDS.contains(&t));
+        let is_path_root = prev != Some("::") && token(Some(i + 1)) == Some("::");
+        let is_extern_crate = prev2 == Some("extern") && prev == Some("crate");
+        if is_global_path || is_path_root || is_extern_crate {
+            crates.insert(ident.clone());
+        }
+    }
+    crates
+}
+
+/// The first identifier of each path a `use` item imports, given the tokens
+/// after `use`, e.g. `serde_json` in `use serde_json as json;` and both `a`
+/// and `b` in `use {a::X, b};`.
+fn use_roots(tokens: &[String]) -> Vec<String> {
+    let tokens = match tokens.first().map(String::as_str) {
+        Some("::") => &tokens[1..],
+        _ => tokens,
+    };
+    match tokens.first().map(String::as_str) {
+        Some("{") => {}
+        Some(ident) if is_ident(ident) && !KEYWORDS.contains(&ident) => {
+            return vec![ident.to_owned()]
+        }
+        _ => return Vec::new(),
+    }
+
+    // Within the group, a path starts after its opening brace or a comma.
+    let mut roots = Vec::new();
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" if depth == 1 => break,
+            "}" => depth -= 1,
+            ";" => break,
+            ident if depth == 1 && is_ident(ident) && !KEYWORDS.contains(&ident) => {
+                let prev = tokens[i - 1].as_str();
+                let prev = match prev {
+                    "::" if i >= 2 && matches!(tokens[i - 2].as_str(), "{" | ",") => {
+                        tokens[i - 2].as_str()
+                    }
+                    prev => prev,
+                };
+                if prev == "{" || prev == "," {
+                    roots.push(ident.to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    roots
+}
+
+/// Keywords that may come right before a path, but never start one.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "const", "dyn", "else", "enum", "extern", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+    "trait", "type", "unsafe", "use", "where", "while",
+];
+
+fn is_ident(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+/// Splits source into identifiers, `::` and single punctuation characters,
+/// dropping comments and literals.
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('/') if chars.peek() == Some(&'*') => {
+                            chars.next();
+                            depth += 1;
+                        }
+                        Some('*') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            depth -= 1;
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+            }
+            '"' => {
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        _ => escaped = false,
+                    }
+                }
+            }
+            // Skip character literals, but not lifetimes.
+            '\'' if chars.peek() == Some(&'\\') => {
+                chars.next();
+                chars.next();
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '\'' if chars.clone().nth(1) == Some('\'') => {
+                chars.next();
+                chars.next();
+            }
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                tokens.push("::".to_owned());
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(ident);
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+#[test]
+fn test_used_crates() {
+    let source = r#"
+        //! Uses `ignored::Comment`.
+        extern crate libc;
+        use serde::Serialize;
+        use ::log as _;
+        use std::{fmt, io::Write};
+
+        /* nested /* block::comment */ still::comment */
+        const MESSAGE: &str = "not_a::crate";
+        const QUOTE: char = '"';
+        fn f<'a>(_: &'a str) -> Option<u8> { core::option::Option::None }
+
+        #[derive(Serialize)]
+        struct S(serde_json::Value, crate::Local, self::Local);
+
+        use serde_yaml as yaml;
+        pub use {rand, libc as c, ::either::Either, nested::{inner, deeper::X}};
+        pub(crate) use {regex};
+    "#;
+    let crates: Vec<String> = used_crates(source).into_iter().collect();
+    assert_eq!(
+        crates,
+        [
+            "core",
+            "crate",
+            "deeper",
+            "either",
+            "io",
+            "libc",
+            "log",
+            "nested",
+            "rand",
+            "regex",
+            "self",
+            "serde",
+            "serde_json",
+            "serde_yaml",
+            "std"
+        ]
+    );
+}
+
+#[test]
+fn test_macro_and_derive_paths() {
+    let source = r#"
+        #[derive(Debug, zerocopy::AsBytes)]
+        #[cfg_attr(feature = "pod", derive(bytemuck::Pod))]
+        struct S;
+
+        fn f() -> anyhow::Result<()> {
+            let _ = serde_json::json!({ "a": 1 });
+            log::info!("called");
+            Ok(())
+        }
+    "#;
+    let crates: Vec<String> = used_crates(source).into_iter().collect();
+    assert_eq!(
+        crates,
+        ["anyhow", "bytemuck", "log", "serde_json", "zerocopy"]
+    );
+}
@@ -0,0 +1,3 @@
+fn main() {
+    test_cdylib::export_features();
+}
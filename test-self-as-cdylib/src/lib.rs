@@ -2,3 +2,9 @@
 pub extern "C" fn identity(x: i32) -> i32 {
     x
 }
+
+#[cfg(feature = "extended-api")]
+#[no_mangle]
+pub extern "C" fn extended() -> i32 {
+    1
+}
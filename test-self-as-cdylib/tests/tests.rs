@@ -1,3 +1,5 @@
+#![allow(clippy::expect_fun_call)]
+
 #[test]
 pub fn load_lib() {
     let dylib = test_cdylib::build_current_project();
@@ -10,3 +12,15 @@ pub fn load_lib() {
     };
     assert_eq!(identity(1), 1);
 }
+
+#[test]
+pub fn exported_features() {
+    // Set by the build script.
+    assert!(std::env::var_os("TEST_CDYLIB_FEATURES").is_some());
+
+    let dylib = test_cdylib::build_current_project();
+    let dylib = dlopen::symbor::Library::open(&dylib)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", dylib.display()));
+    let extended = unsafe { dylib.symbol::<extern "C" fn() -> i32>("extended") };
+    assert_eq!(extended.is_ok(), cfg!(feature = "extended-api"));
+}
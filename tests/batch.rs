@@ -0,0 +1,29 @@
+use std::path::Path;
+
+#[test]
+pub fn build_files() {
+    let outputs = test_cdylib::build_files(&[
+        "tests/cdylibs/identity.rs",
+        "tests/cdylibs/broken.rs",
+        "tests/cdylibs/missing.rs",
+    ]);
+    assert_eq!(outputs.len(), 3);
+    assert!(outputs[Path::new("tests/cdylibs/identity.rs")]
+        .as_ref()
+        .unwrap()
+        .is_file());
+    assert!(matches!(
+        outputs[Path::new("tests/cdylibs/broken.rs")],
+        Err(test_cdylib::Error::CompileError { .. })
+    ));
+    assert!(matches!(
+        outputs[Path::new("tests/cdylibs/missing.rs")],
+        Err(test_cdylib::Error::Open(..))
+    ));
+}
+
+#[test]
+pub fn build_examples() {
+    let outputs = test_cdylib::build_examples(&["test_example"]);
+    assert!(outputs["test_example"].as_ref().unwrap().is_file());
+}
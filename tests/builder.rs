@@ -0,0 +1,292 @@
+use test_cdylib::CdylibBuilder;
+
+#[test]
+pub fn build_file_with_options() {
+    let output = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .env("TEST_CDYLIB_UNUSED", "1")
+        .cargo_arg("--quiet")
+        .build()
+        .unwrap();
+    assert!(output.artifact.is_file());
+    // The artifact is a copy of one of the files cargo built.
+    let file_name = output.artifact.file_name();
+    assert!(output
+        .filenames
+        .iter()
+        .any(|path| path.file_name() == file_name));
+    assert!(output.package_id.contains("test-cdylib-cdylib-identity"));
+    assert_eq!(output.target_name, "test_cdylib_cdylib_identity");
+    assert_eq!(output.crate_types, ["cdylib"]);
+}
+
+#[test]
+pub fn build_package() {
+    let output = CdylibBuilder::package("test-self-as-cdylib")
+        .build()
+        .unwrap();
+    assert!(output.package_id.contains("test-self-as-cdylib"));
+    assert_eq!(output.target_name, "test_self_as_cdylib");
+    let dylib = dlopen::symbor::Library::open(&output.artifact)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", output.artifact.display()));
+    let identity = unsafe {
+        dylib
+            .symbol::<extern "C" fn(i32) -> i32>("identity")
+            .unwrap()
+    };
+    assert_eq!(identity(1), 1);
+
+    let err = CdylibBuilder::package("missing").build().unwrap_err();
+    assert!(matches!(
+        err,
+        test_cdylib::Error::PackageNotFound { name } if name == "missing"
+    ));
+}
+
+#[test]
+pub fn build_crate_at() {
+    let dylib_path = test_cdylib::build_crate_at("test-self-as-cdylib");
+    assert!(dylib_path.is_file());
+    let output = CdylibBuilder::crate_at("test-self-as-cdylib/Cargo.toml")
+        .build()
+        .unwrap();
+    assert_eq!(output.artifact, dylib_path);
+
+    let err = test_cdylib::try_build_crate_at("missing").unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::Open(..)));
+}
+
+#[test]
+pub fn build_file_with_profiles() {
+    let builder = CdylibBuilder::file("tests/cdylibs/identity.rs");
+    let debug = builder.clone().build().unwrap();
+    let release = builder.clone().release().build().unwrap();
+    let custom = builder.profile("ffi-release").build().unwrap();
+
+    assert_eq!(debug.profile, "dev");
+    assert_eq!(release.profile, "release");
+    assert_eq!(custom.profile, "ffi-release");
+    // The artifact is copied into a directory of the profile's.
+    let profile_dir = |output: &test_cdylib::BuildOutput| {
+        output
+            .artifact
+            .parent()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_owned()
+    };
+    assert!(profile_dir(&debug).ends_with("debug"));
+    assert!(profile_dir(&release).ends_with("release"));
+    assert!(profile_dir(&custom).ends_with("ffi-release"));
+    assert_ne!(debug.artifact, release.artifact);
+}
+
+#[test]
+pub fn build_file_with_build_script() {
+    let output = CdylibBuilder::file("tests/cdylibs/build_script.rs")
+        .build_script("tests/cdylibs/build_script_build.rs")
+        .build()
+        .unwrap();
+    let dylib = dlopen::symbor::Library::open(&output.artifact)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", output.artifact.display()));
+    let value = unsafe { dylib.symbol::<extern "C" fn() -> i32>("value").unwrap() };
+    assert_eq!(value(), 7);
+}
+
+#[test]
+pub fn build_file_concurrently() {
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            std::thread::spawn(|| {
+                CdylibBuilder::file("tests/cdylibs/identity.rs")
+                    .build()
+                    .unwrap()
+                    .artifact
+            })
+        })
+        .collect();
+    let artifacts: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert!(artifacts.iter().all(|artifact| *artifact == artifacts[0]));
+}
+
+#[test]
+pub fn build_file_with_manifest_overlay() {
+    let output = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .manifest_overlay_str("lib.name = \"overlay_plugin\"")
+        .build()
+        .unwrap();
+    let file_stem = output.artifact.file_stem().unwrap().to_string_lossy();
+    assert!(file_stem.ends_with("overlay_plugin"));
+
+    let err = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .manifest_overlay_str("package.publish = true")
+        .build()
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        test_cdylib::Error::ManifestConflict { key } if key == "package.publish"
+    ));
+}
+
+#[test]
+pub fn build_file_next_to_broken_fixture() {
+    let err = CdylibBuilder::source("unresolvable", "")
+        .manifest_overlay_str("dependencies.test-cdylib-does-not-exist = \"1\"")
+        .build()
+        .unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::CompileError { .. }));
+
+    // The broken fixture isn't a member of the workspace for other builds.
+    let output = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .build()
+        .unwrap();
+    assert!(output.artifact.is_file());
+}
+
+#[test]
+pub fn build_source_with_pruned_dependencies() {
+    let fixture_manifest = |output: &test_cdylib::BuildOutput, name: &str| {
+        // The artifact is in `<workspace>/target/debug/<config>`.
+        let workspace = output.artifact.ancestors().nth(4).unwrap();
+        let fixtures = std::fs::read_dir(workspace.join("fixtures")).unwrap();
+        let fixture = fixtures
+            .map(|entry| entry.unwrap().path())
+            .find(|dir| dir.file_name().unwrap().to_string_lossy().starts_with(name))
+            .unwrap();
+        std::fs::read_to_string(fixture.join("Cargo.toml")).unwrap()
+    };
+    let code =
+        "#[no_mangle] pub extern \"C\" fn parsed() -> i32 { serde_json::from_str(\"7\").unwrap() }";
+
+    let dependencies = |output: &test_cdylib::BuildOutput, name: &str| {
+        let manifest: toml::Value = toml::from_str(&fixture_manifest(output, name)).unwrap();
+        manifest["dependencies"].as_table().unwrap().clone()
+    };
+
+    let output = CdylibBuilder::source("pruned", code).build().unwrap();
+    let pruned = dependencies(&output, "pruned-");
+    assert!(pruned.contains_key("serde_json"));
+    assert!(pruned.contains_key("test-cdylib"));
+    assert!(!pruned.contains_key("serde"));
+    assert!(!pruned.contains_key("dlopen"));
+    assert!(!pruned.contains_key("cargo_metadata"));
+
+    // A dependency only used through a derive macro is kept too.
+    let derive_code = "#[derive(serde::Serialize)] pub struct Point { x: i32 } \
+        #[no_mangle] pub extern \"C\" fn origin() -> i32 { Point { x: 0 }.x }";
+    let output = CdylibBuilder::source("derived", derive_code)
+        .build()
+        .unwrap();
+    let derived = dependencies(&output, "derived-");
+    assert!(derived.contains_key("serde"));
+    assert!(!derived.contains_key("serde_json"));
+
+    let output = CdylibBuilder::source("unpruned", code)
+        .all_dependencies()
+        .build()
+        .unwrap();
+    assert!(fixture_manifest(&output, "unpruned-").contains("dlopen"));
+}
+
+#[test]
+pub fn build_feature_matrix() {
+    let has_extended = |output: &test_cdylib::BuildOutput| {
+        let dylib = dlopen::symbor::Library::open(&output.artifact)
+            .unwrap_or_else(|_| panic!("failed to open library: {}", output.artifact.display()));
+        let extended = unsafe { dylib.symbol::<extern "C" fn() -> i32>("extended") };
+        extended.is_ok()
+    };
+
+    let builder = CdylibBuilder::file("tests/cdylibs/feature_matrix.rs");
+    let builds = builder
+        .build_feature_matrix(vec![vec![], vec!["extended"]])
+        .unwrap();
+    assert_eq!(builds[0].0, Vec::<String>::new());
+    assert!(!has_extended(&builds[0].1));
+    assert_eq!(builds[1].0, ["extended"]);
+    assert!(has_extended(&builds[1].1));
+    assert_ne!(builds[0].1.artifact, builds[1].1.artifact);
+
+    let output = builder.clone().no_default_features().build().unwrap();
+    assert!(!has_extended(&output));
+    let output = builder.clone().all_features().build().unwrap();
+    assert!(has_extended(&output));
+
+    // Building a configuration again rebuilds it, even though another one
+    // has overwritten its artifact in the meantime.
+    let output = builder.clone().no_default_features().build().unwrap();
+    assert!(!has_extended(&output));
+}
+
+#[test]
+pub fn build_forced_current_project() {
+    let output = CdylibBuilder::current_project()
+        .force_cdylib()
+        .build()
+        .unwrap();
+    assert!(output.artifact.is_file());
+}
+
+#[test]
+pub fn build_file_for_target() {
+    let host = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .build()
+        .unwrap();
+    assert!(!host.target.is_empty());
+
+    let output = CdylibBuilder::file("tests/cdylibs/identity.rs")
+        .target(&host.target)
+        .build()
+        .unwrap();
+    assert_eq!(output.target, host.target);
+    assert!(output.artifact.is_file());
+    assert!(output
+        .artifact
+        .ancestors()
+        .nth(2)
+        .unwrap()
+        .ends_with(std::path::Path::new(&host.target).join("debug")));
+}
+
+#[test]
+pub fn build_file_with_diagnostics() {
+    let builder = CdylibBuilder::file("tests/cdylibs/warning.rs").quiet();
+    let output = builder.build().unwrap();
+    let warning = output
+        .diagnostics
+        .iter()
+        .find(|d| d.code.as_deref() == Some("improper_ctypes_definitions"))
+        .unwrap();
+    assert!(warning.is_warning());
+    assert!(warning.rendered.is_some());
+    assert_eq!(warning.spans[0].line_start, 2);
+
+    let err = builder.deny_warnings().build().unwrap_err();
+    match err {
+        test_cdylib::Error::Warnings { diagnostics, .. } => {
+            assert!(diagnostics.iter().any(|d| d.is_warning()));
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+pub fn build_source_with_rustflag() {
+    // The fixture workspace's config allows dead code, which the extra flag
+    // mustn't replace.
+    let code = r#"
+        fn unused() {}
+
+        #[cfg(extra)]
+        #[no_mangle]
+        pub extern "C" fn extra() {}
+    "#;
+    let output = CdylibBuilder::source("rustflag", code)
+        .rustflag("--cfg=extra")
+        .rustflag("--check-cfg=cfg(extra)")
+        .deny_warnings()
+        .build()
+        .unwrap();
+    let symbols = test_cdylib::exported_symbols(&output.artifact).unwrap();
+    assert!(symbols.iter().any(|symbol| symbol.name == "extra"));
+}
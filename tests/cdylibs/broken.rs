@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern "C" fn broken() -> i32 {
+    "not an i32"
+}
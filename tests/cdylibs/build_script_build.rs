@@ -0,0 +1,12 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("value.rs"),
+        "#[no_mangle] pub extern \"C\" fn value() -> i32 { 7 }",
+    )
+    .unwrap();
+}
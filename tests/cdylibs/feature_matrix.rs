@@ -0,0 +1,16 @@
+//! ```cargo
+//! [features]
+//! default = ["extended"]
+//! extended = []
+//! ```
+
+#[no_mangle]
+pub extern "C" fn identity(x: i32) -> i32 {
+    x
+}
+
+#[cfg(feature = "extended")]
+#[no_mangle]
+pub extern "C" fn extended() -> i32 {
+    1
+}
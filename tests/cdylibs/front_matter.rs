@@ -0,0 +1,14 @@
+//! A fixture with its own dependencies.
+//!
+//! ```cargo
+//! [dependencies]
+//! libc = "0.2"
+//!
+//! [lib]
+//! name = "plugin"
+//! ```
+
+#[no_mangle]
+pub extern "C" fn page_size() -> libc::c_long {
+    4096
+}
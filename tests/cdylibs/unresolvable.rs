@@ -0,0 +1,6 @@
+//! A fixture whose dependencies can't be resolved.
+//!
+//! ```cargo
+//! [dependencies]
+//! test-cdylib-does-not-exist = "1"
+//! ```
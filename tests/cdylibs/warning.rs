@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern "C" fn length(s: String) -> usize {
+    s.len()
+}
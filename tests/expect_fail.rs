@@ -0,0 +1,19 @@
+#[test]
+pub fn broken_file() {
+    test_cdylib::build_file_expect_fail("tests/cdylibs/broken.rs");
+}
+
+#[test]
+pub fn working_file() {
+    let err = test_cdylib::try_build_file_expect_fail("tests/cdylibs/identity.rs").unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::UnexpectedSuccess { .. }));
+}
+
+#[test]
+pub fn unresolvable_file() {
+    // There's no compiler output to compare, so this mustn't write or match an
+    // empty `.stderr` file.
+    let err = test_cdylib::try_build_file_expect_fail("tests/cdylibs/unresolvable.rs").unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::CompileError { .. }));
+    assert!(!std::path::Path::new("tests/cdylibs/unresolvable.stderr").exists());
+}
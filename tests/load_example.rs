@@ -1,3 +1,5 @@
+#![allow(clippy::expect_fun_call)]
+
 #[test]
 pub fn load_example() {
     let dylib = test_cdylib::build_example("test_example");
@@ -16,3 +18,46 @@ pub fn load_example() {
 pub fn missing_example() {
     test_cdylib::build_example("missing");
 }
+
+#[test]
+pub fn try_missing_example() {
+    match test_cdylib::try_build_example("missing") {
+        Err(test_cdylib::Error::CompileError {
+            command, stderr, ..
+        }) => {
+            assert!(command.contains("--example missing"));
+            assert!(stderr.contains("missing"));
+        }
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn load_forced_example() {
+    use test_cdylib::{CdylibBuilder, Error};
+
+    let err = CdylibBuilder::example("rlib_example").build().unwrap_err();
+    assert!(matches!(err, Error::CdylibNotFound { .. }));
+
+    let output = CdylibBuilder::example("rlib_example")
+        .force_cdylib()
+        .build()
+        .unwrap();
+    let dylib = dlopen::symbor::Library::open(&output.artifact)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", output.artifact.display()));
+    let identity = unsafe {
+        dylib
+            .symbol::<extern "C" fn(i32) -> i32>("identity")
+            .unwrap()
+    };
+    assert_eq!(identity(1), 1);
+}
+
+#[test]
+pub fn staticlib_example() {
+    // The host supports cdylibs, so a static library isn't one.
+    let err = test_cdylib::CdylibBuilder::example("staticlib_example")
+        .build()
+        .unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::CdylibNotFound { .. }));
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::expect_fun_call)]
+
 #[test]
 pub fn load_lib() {
     let dylib = test_cdylib::build_file("tests/cdylibs/identity.rs");
@@ -10,3 +12,20 @@ pub fn load_lib() {
     };
     assert_eq!(identity(1), 1);
 }
+
+#[test]
+pub fn try_missing_file() {
+    match test_cdylib::try_build_file("tests/cdylibs/missing.rs") {
+        Err(test_cdylib::Error::Open(path, _)) => {
+            assert!(path.ends_with("missing.rs"));
+        }
+        other => panic!("expected an open error, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn load_front_matter() {
+    let dylib = test_cdylib::build_file("tests/cdylibs/front_matter.rs");
+    let file_stem = dylib.file_stem().unwrap().to_string_lossy();
+    assert!(file_stem.ends_with("plugin"), "{}", dylib.display());
+}
@@ -0,0 +1,29 @@
+#[test]
+pub fn load_source() {
+    let dylib = test_cdylib::build_source(
+        "identity",
+        "#[no_mangle] pub extern \"C\" fn identity(x: i32) -> i32 { x }",
+    );
+    let dylib = dlopen::symbor::Library::open(&dylib)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", dylib.display()));
+    let identity = unsafe {
+        dylib
+            .symbol::<extern "C" fn(i32) -> i32>("identity")
+            .unwrap()
+    };
+    assert_eq!(identity(1), 1);
+}
+
+#[test]
+pub fn load_macro() {
+    let dylib = test_cdylib::cdylib! {
+        #[no_mangle]
+        pub extern "C" fn double(x: i32) -> i32 {
+            x * 2
+        }
+    };
+    let dylib = dlopen::symbor::Library::open(&dylib)
+        .unwrap_or_else(|_| panic!("failed to open library: {}", dylib.display()));
+    let double = unsafe { dylib.symbol::<extern "C" fn(i32) -> i32>("double").unwrap() };
+    assert_eq!(double(2), 4);
+}
@@ -0,0 +1,53 @@
+use test_cdylib::{SymbolBinding, SymbolKind, SymbolVisibility};
+
+#[test]
+pub fn exported_symbols() {
+    let dylib_path = test_cdylib::build_file("tests/cdylibs/identity.rs");
+    let symbols = test_cdylib::exported_symbols(&dylib_path).unwrap();
+    let identity = symbols
+        .iter()
+        .find(|symbol| symbol.name == "identity")
+        .unwrap();
+    assert_eq!(identity.kind, SymbolKind::Function);
+    assert_eq!(identity.binding, SymbolBinding::Global);
+    assert_eq!(identity.visibility, SymbolVisibility::Default);
+    assert_eq!(identity.demangled, None);
+
+    let functions: Vec<&str> = symbols
+        .iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Function)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+    assert_eq!(functions, ["identity"]);
+}
+
+#[test]
+pub fn exported_symbols_of_missing_file() {
+    let err = test_cdylib::exported_symbols("tests/cdylibs/missing.so").unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::Open(..)));
+}
+
+#[test]
+pub fn assert_exports() {
+    let dylib_path = test_cdylib::build_file("tests/cdylibs/identity.rs");
+    test_cdylib::assert_exports(&dylib_path, "identity");
+}
+
+#[test]
+pub fn assert_exports_mismatch() {
+    // The snapshot is wrong on purpose, so don't let overwrite mode fix it.
+    if std::env::var_os("TEST_CDYLIB").is_some_and(|mode| mode == "overwrite") {
+        return;
+    }
+
+    let dylib_path = test_cdylib::build_file("tests/cdylibs/identity.rs");
+    let err = test_cdylib::try_assert_exports(&dylib_path, "identity_renamed").unwrap_err();
+    assert!(matches!(err, test_cdylib::Error::SnapshotMismatch { .. }));
+    let message = err.to_string();
+    assert!(
+        message.contains("-function identity_renamed\n"),
+        "{}",
+        message
+    );
+    assert!(message.contains("+function identity\n"), "{}", message);
+}